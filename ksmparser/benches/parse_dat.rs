@@ -0,0 +1,55 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use ksmparser::measurement::parse_dat_file;
+use ksmparser::ParseOptions;
+use std::fmt::Write;
+use std::fs;
+use std::path::PathBuf;
+
+/// Number of entries in the synthetic .dat file
+const ENTRIES: usize = 20_000;
+
+/// Writes a synthetic .dat file resembling production data and returns its path.
+///
+/// Every hundredth entry carries an extra gauge channel, so columns appear late and
+/// disappear again the same way they do in real files.
+fn create_synthetic_dat_file() -> PathBuf {
+    let columns = "\"measure_time1970\"\t\"wall_min\"\t\"wall_mean\"\t\"diameter_outer_mean\"\t\"ovality\"\t\"info4\"\t\"info5\"\t\"checkresult\"";
+    let mut content = String::new();
+
+    for i in 0..ENTRIES {
+        let values = format!(
+            "{}\t{:.3}\t{:.3}\t{:.3}\t{:.2}\tLine 3\tAnna\t0",
+            1_700_000_000 + i * 5,
+            0.2 + (i % 7) as f64 * 0.001,
+            0.25 + (i % 5) as f64 * 0.001,
+            1.05 + (i % 11) as f64 * 0.001,
+            (i % 13) as f64 * 0.1,
+        );
+        if i % 100 == 0 {
+            writeln!(content, "{}\t\"area_wall\"\n{}\t0.32", columns, values).unwrap();
+        } else {
+            writeln!(content, "{}\n{}", columns, values).unwrap();
+        }
+    }
+
+    let path = std::env::temp_dir().join(format!("ksmparser_bench_{}.dat", std::process::id()));
+    fs::write(&path, content).unwrap();
+    path
+}
+
+fn parse_large_dat_file(c: &mut Criterion) {
+    let path = create_synthetic_dat_file();
+    let options = ParseOptions::default();
+
+    let mut group = c.benchmark_group("parse_dat_file");
+    group.sample_size(10);
+    group.bench_function(format!("{} entries", ENTRIES), |b| {
+        b.iter(|| parse_dat_file(&path, &options).unwrap())
+    });
+    group.finish();
+
+    fs::remove_file(&path).unwrap();
+}
+
+criterion_group!(benches, parse_large_dat_file);
+criterion_main!(benches);
@@ -0,0 +1,187 @@
+/// Module for aggregating measured values over time buckets, e.g. hourly means for trend charts.
+///
+/// Buckets are formed over the `local_time` column, so their boundaries follow the time zone of
+/// the parsed measurements: daily buckets start at local midnight, also across daylight saving
+/// time changes.
+use super::QueryError;
+use polars::prelude::*;
+use std::fmt;
+use std::str::FromStr;
+
+/// Name of the column the buckets are formed over.
+pub const TIME_COLUMN: &str = "local_time";
+
+/// Aggregations computed when no aggregations are given.
+pub const DEFAULT_AGGREGATIONS: [Aggregation; 4] = [
+    Aggregation::Mean,
+    Aggregation::Min,
+    Aggregation::Max,
+    Aggregation::Count,
+];
+
+/// Aggregation of the values of a column within a bucket
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aggregation {
+    /// Number of non-null values
+    Count,
+    Mean,
+    Median,
+    Min,
+    Max,
+    /// Sample standard deviation
+    Std,
+    Sum,
+}
+
+impl Aggregation {
+    /// Returns the name of the aggregation, used as suffix of the result columns.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Aggregation::Count => "count",
+            Aggregation::Mean => "mean",
+            Aggregation::Median => "median",
+            Aggregation::Min => "min",
+            Aggregation::Max => "max",
+            Aggregation::Std => "std",
+            Aggregation::Sum => "sum",
+        }
+    }
+
+    /// Returns the name of the result column of an aggregated column, e.g. `wall_min_mean`.
+    pub fn column_name(&self, column: &str) -> String {
+        format!("{}_{}", column, self.name())
+    }
+
+    fn expr(&self, column: &str) -> Expr {
+        let values = col(column);
+        let expr = match self {
+            Aggregation::Count => values.count(),
+            Aggregation::Mean => values.mean(),
+            Aggregation::Median => values.median(),
+            Aggregation::Min => values.min(),
+            Aggregation::Max => values.max(),
+            Aggregation::Std => values.std(1),
+            Aggregation::Sum => values.sum(),
+        };
+        expr.alias(self.column_name(column))
+    }
+}
+
+impl FromStr for Aggregation {
+    type Err = QueryError;
+
+    /// Parses the name of an aggregation: count, mean, median, min, max, std or sum.
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "count" => Ok(Aggregation::Count),
+            "mean" | "avg" => Ok(Aggregation::Mean),
+            "median" => Ok(Aggregation::Median),
+            "min" => Ok(Aggregation::Min),
+            "max" => Ok(Aggregation::Max),
+            "std" => Ok(Aggregation::Std),
+            "sum" => Ok(Aggregation::Sum),
+            _ => Err(QueryError::UnknownAggregation {
+                name: name.to_string(),
+            }),
+        }
+    }
+}
+
+impl fmt::Display for Aggregation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Aggregates measured columns over time buckets of the `local_time` column.
+///
+/// # Arguments
+/// * `lazyframe` - Measurement entries, already filtered to the rows of interest.
+/// * `every` - Length of the buckets as a Polars duration, e.g. `1h`, `8h` or `1d`.
+/// * `offset` - Shift of the bucket boundaries from local midnight, e.g. `6h` for shifts
+///   starting at 06:00. Also a Polars duration, and may be negative, e.g. `-2h`.
+/// * `columns` - Names of the numeric columns to aggregate.
+/// * `aggregations` - Aggregations computed for every column.
+///
+/// # Returns
+/// * `Result<DataFrame, QueryError>` - One row per bucket containing measurements, with the
+///   start of the bucket in `local_time` and a `<column>_<aggregation>` column for every
+///   column and aggregation, sorted by time.
+///
+/// # Errors
+/// * `QueryError::InvalidArgument` if `every` or `offset` is not a valid duration, `every` is
+///   not positive, or a column is not numeric.
+/// * `QueryError::UnknownColumn` if the measurements have no `local_time` column or no column
+///   of `columns`.
+/// * `QueryError::ComputationError` if the aggregation fails.
+pub fn aggregate(
+    mut lazyframe: LazyFrame,
+    every: &str,
+    offset: &str,
+    columns: &[&str],
+    aggregations: &[Aggregation],
+) -> Result<DataFrame, QueryError> {
+    let invalid = |argument: &str, value: &str, reason: &str| QueryError::InvalidArgument {
+        argument: argument.to_string(),
+        value: value.to_string(),
+        reason: reason.to_string(),
+    };
+    let every_duration = Duration::try_parse(every)
+        .map_err(|_| invalid("bucket length", every, "Not a duration such as 1h or 1d"))?;
+    if every_duration.negative() || every_duration.is_zero() {
+        return Err(invalid("bucket length", every, "Not positive"));
+    }
+    let offset = Duration::try_parse(offset)
+        .map_err(|_| invalid("bucket offset", offset, "Not a duration such as 6h or -2h"))?;
+
+    let schema = lazyframe
+        .collect_schema()
+        .map_err(|source| QueryError::ComputationError { source })?;
+    if schema.get(TIME_COLUMN).is_none() {
+        return Err(QueryError::UnknownColumn {
+            column: TIME_COLUMN.to_string(),
+        });
+    }
+    for column in columns {
+        match schema.get(column) {
+            Some(dtype) if dtype.is_primitive_numeric() => {}
+            Some(dtype) => {
+                let reason = format!("Column of type {} is not numeric", dtype);
+                return Err(invalid("column", column, &reason));
+            }
+            None => {
+                return Err(QueryError::UnknownColumn {
+                    column: column.to_string(),
+                })
+            }
+        }
+    }
+
+    let expressions: Vec<Expr> = columns
+        .iter()
+        .flat_map(|column| {
+            aggregations
+                .iter()
+                .map(move |aggregation| aggregation.expr(column))
+        })
+        .collect();
+
+    // Buckets require the time column to be sorted and without nulls
+    lazyframe
+        .filter(col(TIME_COLUMN).is_not_null())
+        .sort([TIME_COLUMN], SortMultipleOptions::default())
+        .group_by_dynamic(
+            col(TIME_COLUMN),
+            [],
+            DynamicGroupOptions {
+                every: every_duration,
+                period: every_duration,
+                offset,
+                start_by: StartBy::WindowBound,
+                ..Default::default()
+            },
+        )
+        .agg(expressions)
+        .collect()
+        .map_err(|source| QueryError::ComputationError { source })
+}
@@ -0,0 +1,178 @@
+/// Module for reading KSM files stored in zip archives.
+///
+/// Older measurement history is often rotated into archives, e.g. one `.zip` per month. The
+/// entries of an archive are listed with the same patterns as files in a folder and parsed
+/// with the same functions as uncompressed files.
+use super::discovery::Discovery;
+use super::key::KsmFileKey;
+use super::{FolderParseResult, Location, ParseError, ParseOptions};
+use crate::{article, measurement};
+use polars::prelude::DataFrame;
+use rayon::prelude::*;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+use zip::ZipArchive;
+
+/// A KSM file stored in a zip archive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveEntry {
+    /// Article number and variant of the file
+    pub key: KsmFileKey,
+    /// Name of the entry in the archive, including any folders
+    pub name: String,
+    /// Uncompressed size in bytes
+    pub len: u64,
+}
+
+impl ArchiveEntry {
+    /// Returns the file name of the entry without the folders inside the archive.
+    pub fn file_name(&self) -> &str {
+        match self.name.rsplit_once('/') {
+            Some((_, file_name)) => file_name,
+            None => &self.name,
+        }
+    }
+}
+
+fn open_archive(path: &Path) -> Result<ZipArchive<BufReader<File>>, ParseError> {
+    let file = File::open(path).map_err(|source| ParseError::InvalidFile {
+        location: Location::file(path),
+        source,
+    })?;
+    ZipArchive::new(BufReader::new(file)).map_err(|source| ParseError::ArchiveError {
+        location: Location::file(path),
+        source,
+    })
+}
+
+/// Lists the entries of a zip archive with file names matching `discovery`, sorted by name.
+///
+/// # Errors
+/// * `ParseError::InvalidFile` if the archive cannot be opened.
+/// * `ParseError::ArchiveError` if the archive or one of its entries cannot be read.
+pub fn list_archive<P: AsRef<Path>>(
+    path: P,
+    discovery: &Discovery,
+) -> Result<Vec<ArchiveEntry>, ParseError> {
+    let path = path.as_ref();
+    let mut archive = open_archive(path)?;
+    let mut entries = Vec::new();
+    for index in 0..archive.len() {
+        let file = archive
+            .by_index(index)
+            .map_err(|source| ParseError::ArchiveError {
+                location: Location::file(path),
+                source,
+            })?;
+        if file.is_dir() {
+            continue;
+        }
+        let name = file.name().to_string();
+        let file_name = name.rsplit('/').next().unwrap_or_default();
+        if let Some(key) = discovery.key(file_name) {
+            entries.push(ArchiveEntry {
+                key,
+                len: file.size(),
+                name,
+            });
+        }
+    }
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(entries)
+}
+
+/// Reads the uncompressed content of an entry in a zip archive.
+///
+/// # Errors
+/// * `ParseError::InvalidFile` if the archive cannot be opened.
+/// * `ParseError::ArchiveError` if the archive cannot be read or has no entry named `name`.
+/// * `ParseError::IOError` if the content of the entry cannot be read.
+pub fn read_archive_entry<P: AsRef<Path>>(path: P, name: &str) -> Result<Vec<u8>, ParseError> {
+    let path = path.as_ref();
+    let mut archive = open_archive(path)?;
+    read_entry(&mut archive, path, name)
+}
+
+fn read_entry(
+    archive: &mut ZipArchive<BufReader<File>>,
+    path: &Path,
+    name: &str,
+) -> Result<Vec<u8>, ParseError> {
+    let location = Location::file(path.join(name));
+    let mut file = archive
+        .by_name(name)
+        .map_err(|source| ParseError::ArchiveError {
+            location: location.clone(),
+            source,
+        })?;
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes)
+        .map_err(|source| ParseError::IOError { location, source })?;
+    Ok(bytes)
+}
+
+/// Parses the matching entries of a zip archive in parallel, with a result for every entry.
+///
+/// The entries are read one at a time and parsed on the rayon thread pool. Errors are keyed by
+/// entry name and located at the path of the archive joined with the entry name.
+fn parse_archive(
+    path: &Path,
+    parse_function: fn(bytes: &[u8], options: &ParseOptions) -> Result<DataFrame, ParseError>,
+    file_extension: &str,
+    options: &ParseOptions,
+) -> Result<FolderParseResult, ParseError> {
+    let mut archive = open_archive(path)?;
+    let mut contents = Vec::new();
+    for entry in list_archive(path, &Discovery::new(file_extension)?)? {
+        let bytes = read_entry(&mut archive, path, &entry.name);
+        contents.push((entry, bytes));
+    }
+
+    let results: Vec<_> = contents
+        .into_par_iter()
+        .map(|(entry, bytes)| {
+            let result = bytes.and_then(|bytes| {
+                parse_function(&bytes, options).map_err(|e| e.with_path(path.join(&entry.name)))
+            });
+            (entry, result)
+        })
+        .collect();
+
+    let mut archive = FolderParseResult::default();
+    for (entry, result) in results {
+        match result {
+            Ok(data_frame) => {
+                archive.parsed.insert(entry.key, data_frame);
+            }
+            Err(error) => {
+                archive.failed.insert(entry.name, error);
+            }
+        }
+    }
+    Ok(archive)
+}
+
+/// Parses every .dat entry of a zip archive, with a result for every entry.
+///
+/// # Errors
+/// Only errors opening or listing the archive are returned. Entries that cannot be parsed are
+/// collected in `FolderParseResult::failed`.
+pub fn parse_dat_archive<P: AsRef<Path>>(
+    path: P,
+    options: &ParseOptions,
+) -> Result<FolderParseResult, ParseError> {
+    parse_archive(path.as_ref(), measurement::parse_dat_bytes, "dat", options)
+}
+
+/// Parses every .art entry of a zip archive, with a result for every entry.
+///
+/// # Errors
+/// Only errors opening or listing the archive are returned. Entries that cannot be parsed are
+/// collected in `FolderParseResult::failed`.
+pub fn parse_art_archive<P: AsRef<Path>>(
+    path: P,
+    options: &ParseOptions,
+) -> Result<FolderParseResult, ParseError> {
+    parse_archive(path.as_ref(), article::parse_art_bytes, "art", options)
+}
@@ -4,12 +4,44 @@
 ///
 /// Functions included handle reading from files, parsing content, and validating
 /// program names and key-value pair arrangements within the given article files.
-use super::{parse_folder, ParseError};
-use crate::read_and_decode_lines;
+/// Article parameters can also be written back to the same format with `write_art`, and
+/// converted to and from the typed `Article` struct.
+use super::key::KsmFileKey;
+use super::{parse_folder, FolderParseResult, Location, ParseError, ParseOptions};
+use crate::{check_field, decode_lines, format_column, read_and_decode_lines, write_line};
+use lazy_static::lazy_static;
 use polars::prelude::*;
-use std::collections::HashMap;
-use std::io;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Read, Write};
 use std::path::Path;
+use std::str::FromStr;
+
+lazy_static! {
+    /// Data types of the article parameters that are not strings. Float values may use either a
+    /// decimal comma or a decimal point.
+    static ref ARTICLE_DTYPE: HashMap<&'static str, DataType> = {
+        let mut m = HashMap::new();
+        m.insert("cable_parts", DataType::Int64);
+        m.insert("centervalue", DataType::Float64);
+        m.insert("area_outer", DataType::Float64);
+        m.insert("area_wall", DataType::Float64);
+        m.insert("wall_extra_percent", DataType::Float64);
+        m.insert("check_wall_min_minlimit", DataType::Float64);
+        m.insert("check_wall_min_nomlimit", DataType::Float64);
+        m.insert("check_wall_min_maxlimit", DataType::Float64);
+        m.insert("check_wall_mean_minlimit", DataType::Float64);
+        m.insert("check_wall_mean_nomlimit", DataType::Float64);
+        m.insert("check_wall_mean_maxlimit", DataType::Float64);
+        m.insert("check_diameter_outer_mean_minlimit", DataType::Float64);
+        m.insert("check_diameter_outer_mean_nomlimit", DataType::Float64);
+        m.insert("check_diameter_outer_mean_maxlimit", DataType::Float64);
+        m.insert("check_user2_minlimit", DataType::Float64);
+        m.insert("check_user2_nomlimit", DataType::Float64);
+        m.insert("check_user2_maxlimit", DataType::Float64);
+        m
+    };
+}
 
 /// Parses article parameters from an iterator over lines of text, producing a DataFrame.
 ///
@@ -29,47 +61,83 @@ use std::path::Path;
 /// a `ParseError` is returned to indicate the failure.
 ///
 /// # Errors
-/// The function can return the following errors:
-/// - `ParseError::IOError(String)`: When the function encounters an IO error from the input iterator.
-/// - `ParseError::MissingField(String)`: If a required field such as the "pgm_name" or a "None" terminator is missing.
-/// - `ParseError::ColumnCreationError`: If there is an error adding a new Column to the DataFrame.
-/// - `ParseError::MalformedEntry(String)`: If a line cannot be parsed into a valid key-value format.
+/// The function can return the following errors, each carrying the 1-based line where it occurred:
+/// - `ParseError::IOError`: When the function encounters an IO error from the input iterator.
+/// - `ParseError::MissingField`: If a required field such as the "pgm_name" or a "None" terminator is missing.
+/// - `ParseError::ColumnCreationError`: If there is an error adding a new Column to the DataFrame, e.g. a repeated key.
+/// - `ParseError::MalformedEntry`: If a line cannot be parsed into a valid key-value format.
+/// - `ParseError::TypeConversionError`: If the value of a numeric key is not a number.
 fn read_article_parameters(
-    mut line_res: impl Iterator<Item = io::Result<String>>,
+    line_res: impl Iterator<Item = io::Result<String>>,
 ) -> Result<DataFrame, ParseError> {
     let mut dataframe = DataFrame::default();
+    // Pair every line with its 1-based line number
+    let mut line_res = line_res.enumerate().map(|(index, line)| (index + 1, line));
 
     // Check if the first line (expected to be the program name) exists and validate it
     match line_res.next() {
-        Some(Ok(pgm_name)) => {
+        Some((_, Ok(pgm_name))) => {
             // Trim whitespace from the program name
             let pgm_name = pgm_name.trim();
             // Store the valid program name in the parameters map and remove it from the lines
             let column: Column = Column::new(PlSmallStr::from_str("pgm_name"), [pgm_name]);
-            dataframe = dataframe
-                .hstack(&[column])
-                .map_err(|_| ParseError::ColumnCreationError)?;
+            dataframe =
+                dataframe
+                    .hstack(&[column])
+                    .map_err(|source| ParseError::ColumnCreationError {
+                        location: Location::line(1),
+                        column: "pgm_name".to_string(),
+                        source,
+                    })?;
+        }
+        Some((line_number, Err(source))) => {
+            return Err(ParseError::IOError {
+                location: Location::line(line_number),
+                source,
+            })
+        }
+        None => {
+            return Err(ParseError::MissingField {
+                location: Location::line(1),
+                field: String::from("pgm_name"),
+            })
         }
-        Some(Err(e)) => return Err(ParseError::IOError(e.to_string())),
-        None => return Err(ParseError::MissingField(String::from("pgm_name"))),
     }
 
     // Return an error if the "None" line is missing
     match line_res.next() {
-        Some(Ok(line)) => {
+        Some((line_number, Ok(line))) => {
             if line.trim() != "None" {
-                return Err(ParseError::MissingField("None".to_string()));
+                return Err(ParseError::MissingField {
+                    location: Location::line(line_number),
+                    field: "None".to_string(),
+                });
             }
         }
-        Some(Err(e)) => return Err(ParseError::IOError(e.to_string())),
-        None => return Err(ParseError::IOError("Reading none line".to_string())),
+        Some((line_number, Err(source))) => {
+            return Err(ParseError::IOError {
+                location: Location::line(line_number),
+                source,
+            })
+        }
+        None => {
+            return Err(ParseError::MissingField {
+                location: Location::line(2),
+                field: "None".to_string(),
+            })
+        }
     }
 
     // Iterate over the remaining lines to parse key-value pairs
-    for line in line_res {
+    for (line_number, line) in line_res {
         let line = match line {
             Ok(l) => l,
-            Err(e) => return Err(ParseError::IOError(e.to_string())),
+            Err(source) => {
+                return Err(ParseError::IOError {
+                    location: Location::line(line_number),
+                    source,
+                })
+            }
         };
         //Ignore empty lines
         if line.trim().is_empty() {
@@ -79,14 +147,22 @@ fn read_article_parameters(
         match line.split_once(" = ") {
             Some((key, value)) => {
                 // Trim and insert the parsed key and value into the parameters map
-                let column = Column::new(PlSmallStr::from_str(key.trim()), [value.trim()]);
-                dataframe = dataframe
-                    .hstack(&[column])
-                    .map_err(|_| ParseError::ColumnCreationError)?;
+                let column = parameter_column(key.trim(), value.trim())
+                    .map_err(|e| e.at_line(line_number))?;
+                dataframe = dataframe.hstack(&[column]).map_err(|source| {
+                    ParseError::ColumnCreationError {
+                        location: Location::line(line_number),
+                        column: key.trim().to_string(),
+                        source,
+                    }
+                })?;
             }
             None => {
                 // Return an error if a line does not contain a valid key-value format
-                return Err(ParseError::MalformedEntry(line.to_string()));
+                return Err(ParseError::MalformedEntry {
+                    location: Location::line(line_number),
+                    reason: format!("Expected 'key = value', found '{}'", line),
+                });
             }
         }
     }
@@ -98,13 +174,15 @@ fn read_article_parameters(
 ///
 /// This function reads and decodes content of a file at the given path, and attempts
 /// to parse it using `read_article_parameters`. It ensures that the article content
-/// adheres to the expected format and structure.
+/// adheres to the expected format and structure. Files with a `.gz` extension are
+/// decompressed first.
 ///
 /// # Type Parameters
 /// - `P`: The type of file path input, which must implement `AsRef<Path>`, `Display`, and `Copy`.
 ///
 /// # Parameters
 /// - `file_path`: A file path from which to read the article content.
+/// - `options`: Options controlling how the file is decoded.
 ///
 /// # Returns
 /// A `Result` which, on success, contains a `HashMap` of parsed parameters. On failure,
@@ -112,18 +190,413 @@ fn read_article_parameters(
 ///
 /// # Errors
 /// - `InvalidFile`: If the specified file could not be read or decoded.
-/// - Additionally includes all errors thrown by `read_article_parameters`.
-pub fn parse_art_file<P: AsRef<Path>>(file_path: P) -> Result<DataFrame, ParseError> {
-    match read_and_decode_lines(&file_path) {
+/// - Additionally includes all errors thrown by `read_article_parameters`, with the file path
+///   added to their location.
+pub fn parse_art_file<P: AsRef<Path>>(
+    file_path: P,
+    options: &ParseOptions,
+) -> Result<DataFrame, ParseError> {
+    match read_and_decode_lines(&file_path, options) {
         // Attempt to read article parameters from the decoded lines
-        Ok(lines) => read_article_parameters(lines),
+        Ok(lines) => read_article_parameters(lines).map_err(|e| e.with_path(&file_path)),
         // Return an error if the file could not be read and decoded
-        Err(_) => Err(ParseError::InvalidFile(
-            file_path.as_ref().to_string_lossy().into_owned(),
-        )),
+        Err(source) => Err(ParseError::InvalidFile {
+            location: Location::file(&file_path),
+            source,
+        }),
+    }
+}
+
+/// Parses article parameters from any source implementing `Read`.
+///
+/// The content is decoded the same way as in `parse_art_file`, which makes this function suitable
+/// for data that does not live in a file, such as uploads, archive entries or test fixtures.
+///
+/// # Parameters
+/// - `reader`: The source of the article content.
+/// - `options`: Options controlling how the content is decoded.
+///
+/// # Returns
+/// A `Result` containing the parsed parameters as a single-row `DataFrame`, or a `ParseError`.
+///
+/// # Errors
+/// - `IOError`: If the content could not be read before decoding.
+/// - All errors thrown by `read_article_parameters`.
+pub fn parse_art_reader<R: Read>(
+    reader: R,
+    options: &ParseOptions,
+) -> Result<DataFrame, ParseError> {
+    match decode_lines(reader, options) {
+        Ok(lines) => read_article_parameters(lines),
+        Err(source) => Err(ParseError::IOError {
+            location: Location::default(),
+            source,
+        }),
+    }
+}
+
+/// Parses article parameters from an in-memory buffer.
+///
+/// Convenience wrapper around `parse_art_reader`.
+pub fn parse_art_bytes(bytes: &[u8], options: &ParseOptions) -> Result<DataFrame, ParseError> {
+    parse_art_reader(bytes, options)
+}
+
+/// Parses every .art file in a folder, stopping at the first file that cannot be parsed.
+///
+/// Use `parse_art_folder_parallel` to keep the files that could be parsed when some fail.
+pub fn parse_art_folder<P: AsRef<Path>>(
+    dir: P,
+    options: &ParseOptions,
+) -> Result<HashMap<KsmFileKey, DataFrame>, ParseError> {
+    parse_folder(dir, parse_art_file, "art", options)?.into_result()
+}
+
+/// Parses every .art file in a folder in parallel, with a result for every file.
+///
+/// # Errors
+/// Only errors listing the folder are returned. Files that cannot be parsed are collected in
+/// `FolderParseResult::failed`.
+pub fn parse_art_folder_parallel<P: AsRef<Path>>(
+    dir: P,
+    options: &ParseOptions,
+) -> Result<FolderParseResult, ParseError> {
+    parse_folder(dir, parse_art_file, "art", options)
+}
+
+/// Writes article parameters in the .art format, encoded in ISO-8859-10.
+///
+/// The `pgm_name` column is written on the first line, followed by the `None` line and a
+/// `key = value` line for every other column in order. Nulls are written as empty values and
+/// non-string columns are converted to strings. `parse_art_reader` reads back the same
+/// parameters, with numeric keys such as `check_wall_min_minlimit` or `cable_parts` as Float64
+/// or Int64 columns and all other keys as strings.
+///
+/// # Parameters
+/// - `writer`: The destination of the article content.
+/// - `dataframe`: A single-row `DataFrame` of article parameters, as returned by `parse_art_file`.
+///
+/// # Errors
+/// - `MalformedEntry`: If the `DataFrame` does not have exactly one row.
+/// - `MissingField`: If there is no `pgm_name` column.
+/// - `UnwritableValue`: If a key or value cannot be represented in the format, e.g. a line break,
+///   an `=` in a key, surrounding whitespace or a character outside ISO-8859-10.
+/// - `ColumnCreationError`: If a column cannot be converted to strings.
+/// - `WriteError`: If writing to `writer` fails.
+///
+/// All errors carry the 1-based line of the output they relate to where applicable.
+pub fn write_art<W: Write>(mut writer: W, dataframe: &DataFrame) -> Result<(), ParseError> {
+    if dataframe.height() != 1 {
+        return Err(ParseError::MalformedEntry {
+            location: Location::default(),
+            reason: format!(
+                "Expected a single row of article parameters, found {}",
+                dataframe.height()
+            ),
+        });
+    }
+    let value = |column: &Column| -> Result<String, ParseError> {
+        let mut values =
+            format_column(column).map_err(|source| ParseError::ColumnCreationError {
+                location: Location::default(),
+                column: column.name().to_string(),
+                source,
+            })?;
+        Ok(values.pop().flatten().unwrap_or_default())
+    };
+
+    let pgm_name = match dataframe.column("pgm_name") {
+        Ok(column) => value(column)?,
+        Err(_) => {
+            return Err(ParseError::MissingField {
+                location: Location::default(),
+                field: String::from("pgm_name"),
+            })
+        }
+    };
+    check_field(&pgm_name, "pgm_name", 1, &[], true)?;
+    write_line(&mut writer, &pgm_name, 1)?;
+    write_line(&mut writer, "None", 2)?;
+
+    let parameters = dataframe
+        .get_columns()
+        .iter()
+        .filter(|column| column.name() != "pgm_name");
+    for (line_number, column) in (3..).zip(parameters) {
+        let key = column.name().as_str();
+        let value = value(column).map_err(|e| e.at_line(line_number))?;
+        check_field(key, key, line_number, &["="], true)?;
+        check_field(&value, key, line_number, &[], true)?;
+        write_line(&mut writer, &format!("{} = {}", key, value), line_number)?;
     }
+    writer.flush().map_err(|source| ParseError::WriteError {
+        location: Location::default(),
+        source,
+    })
 }
 
-pub fn parse_art_folder<P: AsRef<Path>>(dir: P) -> Result<HashMap<String, DataFrame>, ParseError> {
-    parse_folder(dir, parse_art_file, "art")
+/// Limits of a measured value, given by the `check_<name>_minlimit`, `check_<name>_nomlimit`
+/// and `check_<name>_maxlimit` keys of an article.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Limits {
+    pub min: Option<f64>,
+    pub nom: Option<f64>,
+    pub max: Option<f64>,
+}
+
+impl Limits {
+    /// Returns the limit of the given kind, "min", "nom" or "max".
+    fn get_mut(&mut self, kind: &str) -> Option<&mut Option<f64>> {
+        match kind {
+            "min" => Some(&mut self.min),
+            "nom" => Some(&mut self.nom),
+            "max" => Some(&mut self.max),
+            _ => None,
+        }
+    }
+
+    /// Returns why `value` is outside the minimum and maximum, or `None` if it is within them.
+    /// The nominal value is not a limit and is not checked.
+    pub fn violation(&self, value: f64) -> Option<String> {
+        match (self.min, self.max) {
+            (Some(min), _) if value < min => {
+                Some(format!("{} is below the minimum {}", value, min))
+            }
+            (_, Some(max)) if value > max => {
+                Some(format!("{} is above the maximum {}", value, max))
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the limits with a missing minimum or maximum mirrored around the nominal value,
+    /// e.g. a minimum of 0.1 and a nominal value of 0.13 give a maximum of 0.16.
+    ///
+    /// Limits without a nominal value or with both a minimum and a maximum are unchanged.
+    pub fn symmetric(&self) -> Limits {
+        match (self.min, self.nom, self.max) {
+            (Some(min), Some(nom), None) => Limits {
+                max: Some(2.0 * nom - min),
+                ..*self
+            },
+            (None, Some(nom), Some(max)) => Limits {
+                min: Some(2.0 * nom - max),
+                ..*self
+            },
+            _ => *self,
+        }
+    }
+}
+
+/// Article parameters with typed values.
+///
+/// Created from the single-row `DataFrame` returned by `parse_art_file` with
+/// `Article::from_dataframe` and converted back with `Article::to_dataframe`. Keys without a
+/// dedicated field are kept as strings in `other`. Empty numeric values are treated as missing.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Article {
+    /// Name of the measurement program, the first line of the file
+    pub pgm_name: String,
+    pub info1: Option<String>,
+    pub info2: Option<String>,
+    pub info3: Option<String>,
+    pub info4: Option<String>,
+    pub info5: Option<String>,
+    /// Article number
+    pub info6: Option<String>,
+    /// Conductor material
+    pub material_core: Option<String>,
+    pub cable_parts: Option<i64>,
+    /// Limits keyed by the name of the measured value, e.g. `wall_min` for `check_wall_min_minlimit`
+    pub limits: BTreeMap<String, Limits>,
+    /// Parameters without a dedicated field
+    pub other: BTreeMap<String, String>,
+}
+
+impl Article {
+    /// Creates an article from a single-row `DataFrame` of article parameters.
+    ///
+    /// # Errors
+    /// - `MalformedEntry`: If the `DataFrame` does not have exactly one row.
+    /// - `MissingField`: If there is no `pgm_name` column.
+    /// - `TypeConversionError`: If `cable_parts` or a limit is not a number.
+    /// - `ColumnCreationError`: If a column cannot be converted to strings.
+    pub fn from_dataframe(dataframe: &DataFrame) -> Result<Article, ParseError> {
+        if dataframe.height() != 1 {
+            return Err(ParseError::MalformedEntry {
+                location: Location::default(),
+                reason: format!(
+                    "Expected a single row of article parameters, found {}",
+                    dataframe.height()
+                ),
+            });
+        }
+
+        let mut article = Article::default();
+        let mut has_pgm_name = false;
+        for column in dataframe.get_columns() {
+            let key = column.name().as_str();
+            let value = format_column(column)
+                .map_err(|source| ParseError::ColumnCreationError {
+                    location: Location::default(),
+                    column: key.to_string(),
+                    source,
+                })?
+                .pop()
+                .flatten();
+
+            match key {
+                "pgm_name" => {
+                    article.pgm_name = value.unwrap_or_default();
+                    has_pgm_name = true;
+                }
+                "info1" => article.info1 = value,
+                "info2" => article.info2 = value,
+                "info3" => article.info3 = value,
+                "info4" => article.info4 = value,
+                "info5" => article.info5 = value,
+                "info6" => article.info6 = value,
+                "material_core" => article.material_core = value,
+                "cable_parts" => article.cable_parts = parse_number(key, value.as_deref(), "i64")?,
+                _ => match split_limit_key(key) {
+                    Some((name, kind)) => {
+                        let limit = parse_number(key, value.as_deref(), "f64")?;
+                        if let Some(slot) = article
+                            .limits
+                            .entry(name.to_string())
+                            .or_default()
+                            .get_mut(kind)
+                        {
+                            *slot = limit;
+                        }
+                    }
+                    None => {
+                        article
+                            .other
+                            .insert(key.to_string(), value.unwrap_or_default());
+                    }
+                },
+            }
+        }
+
+        if !has_pgm_name {
+            return Err(ParseError::MissingField {
+                location: Location::default(),
+                field: String::from("pgm_name"),
+            });
+        }
+        Ok(article)
+    }
+
+    /// Converts the article to a single-row `DataFrame` in the form returned by `parse_art_file`,
+    /// with the same data types.
+    ///
+    /// # Errors
+    /// - `DataFrameCreationError`: If a key in `other` is also produced by another field.
+    /// - `TypeConversionError`: If a value in `other` is not a number but its key is numeric.
+    pub fn to_dataframe(&self) -> Result<DataFrame, ParseError> {
+        let mut parameters: Vec<(String, String)> =
+            vec![("pgm_name".into(), self.pgm_name.clone())];
+        let optional = [
+            ("info1", &self.info1),
+            ("info2", &self.info2),
+            ("info3", &self.info3),
+            ("info4", &self.info4),
+            ("info5", &self.info5),
+            ("info6", &self.info6),
+            ("material_core", &self.material_core),
+        ];
+        for (key, value) in optional {
+            if let Some(value) = value {
+                parameters.push((key.to_string(), value.clone()));
+            }
+        }
+        if let Some(cable_parts) = self.cable_parts {
+            parameters.push(("cable_parts".into(), cable_parts.to_string()));
+        }
+        for (name, limits) in &self.limits {
+            for (kind, limit) in [
+                ("min", limits.min),
+                ("nom", limits.nom),
+                ("max", limits.max),
+            ] {
+                if let Some(limit) = limit {
+                    parameters.push((format!("check_{}_{}limit", name, kind), limit.to_string()));
+                }
+            }
+        }
+        for (key, value) in &self.other {
+            parameters.push((key.clone(), value.clone()));
+        }
+
+        let columns = parameters
+            .iter()
+            .map(|(key, value)| parameter_column(key, value))
+            .collect::<Result<Vec<_>, ParseError>>()?;
+        DataFrame::new(columns).map_err(|source| ParseError::DataFrameCreationError {
+            location: Location::default(),
+            source,
+        })
+    }
+
+    /// Returns the article with the tolerance of every value taken to be symmetric around its
+    /// nominal value, see `Limits::symmetric`.
+    ///
+    /// Only the minimum and maximum are limits, so a value with only one of them is checked on
+    /// one side. Passing the returned article to `evaluate_limits` or `article_capability`
+    /// checks such values on both sides instead.
+    pub fn with_symmetric_limits(&self) -> Article {
+        let mut article = self.clone();
+        for limits in article.limits.values_mut() {
+            *limits = limits.symmetric();
+        }
+        article
+    }
+}
+
+/// Splits a key such as `check_wall_min_minlimit` into the measured value and the kind of limit,
+/// `("wall_min", "min")`.
+fn split_limit_key(key: &str) -> Option<(&str, &str)> {
+    let name = key.strip_prefix("check_")?.strip_suffix("limit")?;
+    let (name, kind) = name.rsplit_once('_')?;
+    match kind {
+        "min" | "nom" | "max" if !name.is_empty() => Some((name, kind)),
+        _ => None,
+    }
+}
+
+/// Creates the column of a single parameter with the data type given by `ARTICLE_DTYPE`.
+/// Keys without a known data type are stored as strings and empty numeric values as null.
+fn parameter_column(key: &str, value: &str) -> Result<Column, ParseError> {
+    let name = PlSmallStr::from_str(key);
+    let column = match ARTICLE_DTYPE.get(key) {
+        Some(DataType::Float64) => {
+            Column::new(name, [parse_number::<f64>(key, Some(value), "f64")?])
+        }
+        Some(DataType::Int64) => Column::new(name, [parse_number::<i64>(key, Some(value), "i64")?]),
+        _ => Column::new(name, [value]),
+    };
+    Ok(column)
+}
+
+/// Parses an optional parameter value as a number, accepting a decimal comma as well as a
+/// decimal point. Empty values are treated as missing.
+fn parse_number<T: FromStr>(
+    key: &str,
+    value: Option<&str>,
+    dtype: &str,
+) -> Result<Option<T>, ParseError> {
+    match value.map(str::trim) {
+        None | Some("") => Ok(None),
+        Some(number) => match number.replacen(',', ".", 1).parse() {
+            Ok(number) => Ok(Some(number)),
+            Err(_) => Err(ParseError::TypeConversionError {
+                location: Location::default(),
+                column: key.to_string(),
+                value: number.to_string(),
+                dtype: dtype.to_string(),
+            }),
+        },
+    }
 }
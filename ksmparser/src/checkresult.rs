@@ -0,0 +1,141 @@
+/// Module for decoding the `checkresult` column written by the gauge.
+///
+/// The gauge stores the outcome of all its checks in a single integer, where a set bit means
+/// that the check belonging to it failed. A `CheckResultMapping` names the bits, and the
+/// measurement parser adds a boolean `check_<name>_failed` column for every named bit. Which bit
+/// belongs to which check is configured on the gauge, so there is no built-in mapping: the
+/// mapping is part of the `ColumnSchema` and is given in a schema file, and no check columns
+/// are added without one.
+use super::{Location, ParseError};
+use polars::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Name of the column holding the check outcomes.
+pub const CHECKRESULT_COLUMN: &str = "checkresult";
+
+/// A named bit of the `checkresult` value.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CheckFlag {
+    /// Name of the check, e.g. `wall_min`
+    pub name: String,
+    /// Position of the bit, where 0 is the least significant bit
+    pub bit: u8,
+}
+
+impl CheckFlag {
+    /// Creates a flag naming the given bit.
+    pub fn new(name: &str, bit: u8) -> Self {
+        CheckFlag {
+            name: name.to_string(),
+            bit,
+        }
+    }
+}
+
+/// Outcome of a single check decoded from a `checkresult` value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CheckOutcome<'a> {
+    /// Name of the check
+    pub name: &'a str,
+    pub failed: bool,
+}
+
+/// Names of the bits of the `checkresult` value. The default mapping names no bits.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CheckResultMapping {
+    checks: Vec<CheckFlag>,
+}
+
+impl CheckResultMapping {
+    /// Creates a mapping of the given checks.
+    ///
+    /// # Errors
+    /// * `ParseError::InvalidSchema` if a bit is above 63 or a name is used more than once.
+    pub fn new(checks: Vec<CheckFlag>) -> Result<Self, ParseError> {
+        for (index, check) in checks.iter().enumerate() {
+            let reason = if check.bit > 63 {
+                format!("Bit {} of check {} is above 63", check.bit, check.name)
+            } else if checks[..index].iter().any(|other| other.name == check.name) {
+                format!("Check {} is mapped more than once", check.name)
+            } else {
+                continue;
+            };
+            return Err(ParseError::InvalidSchema {
+                location: Location::default(),
+                reason,
+            });
+        }
+        Ok(CheckResultMapping { checks })
+    }
+
+    /// Returns the named bits in the order they were given.
+    pub fn checks(&self) -> &[CheckFlag] {
+        &self.checks
+    }
+
+    /// Returns the name of the column of a check, e.g. `check_wall_min_failed`.
+    pub fn column_name(name: &str) -> String {
+        format!("check_{}_failed", name)
+    }
+
+    /// Decodes a `checkresult` value written in decimal or as hexadecimal with a `0x` prefix.
+    ///
+    /// Returns `None` if the value is not an unsigned integer.
+    pub fn decode(&self, checkresult: &str) -> Option<Vec<CheckOutcome<'_>>> {
+        let checkresult = checkresult.trim();
+        let bits = match checkresult.strip_prefix("0x") {
+            Some(hex) => u64::from_str_radix(hex, 16).ok()?,
+            None => checkresult.parse::<u64>().ok()?,
+        };
+        Some(
+            self.checks
+                .iter()
+                .map(|check| CheckOutcome {
+                    name: &check.name,
+                    failed: bits & (1 << check.bit) != 0,
+                })
+                .collect(),
+        )
+    }
+
+    /// Adds a `check_<name>_failed` column for every check, decoded from the `checkresult`
+    /// column. Rows with a null or undecodable value are null in all check columns.
+    ///
+    /// DataFrames without a `checkresult` column are left unchanged, and existing columns with
+    /// the same names are replaced.
+    ///
+    /// # Errors
+    /// * `ParseError::ColumnCreationError` if the `checkresult` column cannot be converted to
+    ///   strings or a check column cannot be added.
+    pub fn add_columns(&self, dataframe: &mut DataFrame) -> Result<(), ParseError> {
+        let column_error = |column: &str, source| ParseError::ColumnCreationError {
+            location: Location::default(),
+            column: column.to_string(),
+            source,
+        };
+        let values = match dataframe.column(CHECKRESULT_COLUMN) {
+            Ok(column) => column
+                .cast(&DataType::String)
+                .map_err(|source| column_error(CHECKRESULT_COLUMN, source))?,
+            Err(_) => return Ok(()),
+        };
+        let decoded: Vec<Option<Vec<CheckOutcome>>> = values
+            .str()
+            .map_err(|source| column_error(CHECKRESULT_COLUMN, source))?
+            .into_iter()
+            .map(|value| value.and_then(|value| self.decode(value)))
+            .collect();
+
+        for (index, check) in self.checks.iter().enumerate() {
+            let name = CheckResultMapping::column_name(&check.name);
+            let failed: Vec<Option<bool>> = decoded
+                .iter()
+                .map(|outcomes| outcomes.as_ref().map(|outcomes| outcomes[index].failed))
+                .collect();
+            dataframe
+                .with_column(Column::new(PlSmallStr::from_str(&name), failed))
+                .map_err(|source| column_error(&name, source))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,101 @@
+/// Module for evaluating measurements against the limits of their article.
+///
+/// The gauge writes its own verdict to the `checkresult` column. The functions here compare the
+/// measured values with the `check_<name>_minlimit` and `check_<name>_maxlimit` parameters of
+/// the article instead, so every row can be checked against the current article parameters.
+///
+/// Like the `statistics` module, only the minimum and maximum are limits. The nominal value
+/// `check_<name>_nomlimit` is the target and does not bound the values, so a value with only a
+/// minimum is checked against the minimum alone. Evaluate `Article::with_symmetric_limits` to
+/// take the tolerance to be symmetric around the nominal value instead.
+use super::article::Article;
+use super::{Location, ParseError};
+use polars::prelude::*;
+
+/// Name of the column holding the reasons a row is outside its limits.
+pub const VIOLATION_COLUMN: &str = "limit_violation";
+
+/// Returns the name of the pass/fail column of a measured value, e.g. `wall_min_pass`.
+pub fn pass_column_name(name: &str) -> String {
+    format!("{}_pass", name)
+}
+
+/// Evaluates every row of a measurement DataFrame against the limits of its article.
+///
+/// For every limited value of `article` that is a column of `dataframe`, e.g. `wall_min` for
+/// `check_wall_min_minlimit`, a boolean `<name>_pass` column is added. A row passes a check if
+/// its value is within the minimum and maximum, and the check is null where the value is null.
+/// The nominal value is not a limit, so a value with only a nominal value is reported as
+/// unchecked: its pass column is null in every row. The `limit_violation` column lists the
+/// reasons of all failed checks of a row separated by `; `, and is null for rows without failed
+/// checks.
+///
+/// Existing columns with the same names are replaced.
+///
+/// # Arguments
+/// * `dataframe` - Measurement entries, as returned by `parse_dat_file`.
+/// * `article` - The article the measurements belong to.
+///
+/// # Returns
+/// * `Result<DataFrame, ParseError>` - The measurements with the added columns.
+///
+/// # Errors
+/// * `ParseError::ColumnCreationError` if a measured value cannot be converted to a float or a
+///   result column cannot be added.
+pub fn evaluate_limits(dataframe: &DataFrame, article: &Article) -> Result<DataFrame, ParseError> {
+    let column_error = |column: &str, source| ParseError::ColumnCreationError {
+        location: Location::default(),
+        column: column.to_string(),
+        source,
+    };
+    let mut result = dataframe.clone();
+    let mut violations: Vec<Vec<String>> = vec![Vec::new(); dataframe.height()];
+
+    for (name, limits) in &article.limits {
+        let checked = limits.min.is_some() || limits.max.is_some();
+        if !checked && limits.nom.is_none() {
+            continue;
+        }
+        let values = match dataframe.column(name) {
+            Ok(column) => column
+                .cast(&DataType::Float64)
+                .map_err(|source| column_error(name, source))?,
+            Err(_) => continue,
+        };
+        let values = values.f64().map_err(|source| column_error(name, source))?;
+
+        let passed: Vec<Option<bool>> = values
+            .iter()
+            .zip(violations.iter_mut())
+            .map(|(value, reasons)| {
+                // Without a minimum or maximum the check stays null, marking it as unchecked
+                if !checked {
+                    return None;
+                }
+                match limits.violation(value?) {
+                    Some(reason) => {
+                        reasons.push(format!("{}: {}", name, reason));
+                        Some(false)
+                    }
+                    None => Some(true),
+                }
+            })
+            .collect();
+        let pass_name = pass_column_name(name);
+        result
+            .with_column(Column::new(PlSmallStr::from_str(&pass_name), passed))
+            .map_err(|source| column_error(&pass_name, source))?;
+    }
+
+    let violations: Vec<Option<String>> = violations
+        .into_iter()
+        .map(|reasons| (!reasons.is_empty()).then(|| reasons.join("; ")))
+        .collect();
+    result
+        .with_column(Column::new(
+            PlSmallStr::from_static(VIOLATION_COLUMN),
+            violations,
+        ))
+        .map_err(|source| column_error(VIOLATION_COLUMN, source))?;
+    Ok(result)
+}
@@ -0,0 +1,320 @@
+/// Module for finding KSM files in a folder.
+///
+/// A `Discovery` lists the files whose names match a pattern, together with the key of the
+/// article variant they belong to and their metadata. It is used by the folder parsing
+/// functions and can be used by applications that keep track of modified files.
+/// Gzip compressed files and the entries of zip archives can be included as well.
+use super::archive;
+use super::key::KsmFileKey;
+use super::{is_gzip, open_file, Location, ParseError};
+use flate2::read::MultiGzDecoder;
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{Cursor, Read};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// A KSM file found by a `Discovery`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiscoveredFile {
+    /// Article number and variant of the file
+    pub key: KsmFileKey,
+    /// Path of the file, or of the zip archive containing it
+    pub path: PathBuf,
+    pub file_name: String,
+    /// Name of the entry in the zip archive at `path`, if the file is stored in an archive
+    pub entry: Option<String>,
+    /// Time of the last modification. Entries of an archive share the time of the archive.
+    pub modified: SystemTime,
+    /// Size of the file in bytes, uncompressed for archive entries
+    pub len: u64,
+}
+
+impl DiscoveredFile {
+    /// Returns the path of the file, with the entry name appended for archive entries,
+    /// e.g. `history/2024-01.zip/00202.dat`.
+    pub fn full_path(&self) -> PathBuf {
+        match &self.entry {
+            Some(entry) => self.path.join(entry),
+            None => self.path.clone(),
+        }
+    }
+
+    /// Returns true if the file is gzip compressed or stored in a zip archive. Such files hold
+    /// rotated history and are not appended to.
+    pub fn is_archived(&self) -> bool {
+        self.entry.is_some() || is_gzip(&self.path)
+    }
+
+    /// Opens the file for reading, decompressing gzip files and extracting archive entries.
+    ///
+    /// # Errors
+    /// * `ParseError::InvalidFile` if the file cannot be opened.
+    /// * `ParseError::ArchiveError` if the archive entry cannot be read.
+    pub fn open(&self) -> Result<Box<dyn Read + Send>, ParseError> {
+        match &self.entry {
+            Some(entry) => {
+                let bytes = Cursor::new(archive::read_archive_entry(&self.path, entry)?);
+                if is_gzip(entry) {
+                    Ok(Box::new(MultiGzDecoder::new(bytes)))
+                } else {
+                    Ok(Box::new(bytes))
+                }
+            }
+            None => open_file(&self.path).map_err(|source| ParseError::InvalidFile {
+                location: Location::file(&self.path),
+                source,
+            }),
+        }
+    }
+}
+
+/// A file left out of a discovery, e.g. because another file has the same key.
+#[derive(Debug)]
+pub struct DiscoveryFailure {
+    /// Path of the file
+    pub path: PathBuf,
+    /// Time of the last modification
+    pub modified: SystemTime,
+    pub error: ParseError,
+}
+
+/// Outcome of listing the files in a folder.
+#[derive(Debug, Default)]
+pub struct DiscoveryResult {
+    /// Matching files, sorted by path and archive entry name
+    pub files: Vec<DiscoveredFile>,
+    /// Matching files that were left out, sorted by path
+    pub failed: Vec<DiscoveryFailure>,
+}
+
+/// Finds KSM files with names matching a pattern.
+///
+/// The article number and variant are taken from the `article` and `variant` capture groups of
+/// the pattern. Patterns without an `article` group use the file name as the key, as accepted by
+/// `KsmFileKey::from_str`. Matching files whose key cannot be determined are left out.
+#[derive(Debug, Clone)]
+pub struct Discovery {
+    pattern: Regex,
+    recursive: bool,
+    compressed: bool,
+    archives: bool,
+}
+
+impl Discovery {
+    /// Creates a discovery of files named after an article number with 3 to 5 digits, an optional
+    /// `-N` variant suffix and the given extension, e.g. `00202-1.dat`.
+    ///
+    /// # Errors
+    /// * `ParseError::InvalidRegex` if the pattern built from the extension is invalid.
+    pub fn new(file_extension: &str) -> Result<Self, ParseError> {
+        Discovery::with_pattern(&format!(
+            r"^(?P<article>\d{{3,5}})(-(?P<variant>\d))?\.{}$",
+            regex::escape(file_extension)
+        ))
+    }
+
+    /// Creates a discovery of files with names matching a regular expression.
+    ///
+    /// # Errors
+    /// * `ParseError::InvalidRegex` if `pattern` is not a valid regular expression.
+    pub fn with_pattern(pattern: &str) -> Result<Self, ParseError> {
+        let pattern = Regex::new(pattern).map_err(|source| ParseError::InvalidRegex {
+            pattern: pattern.to_string(),
+            source,
+        })?;
+        Ok(Discovery {
+            pattern,
+            recursive: false,
+            compressed: false,
+            archives: false,
+        })
+    }
+
+    /// Sets whether subdirectories are searched as well.
+    pub fn recursive(mut self, recursive: bool) -> Self {
+        self.recursive = recursive;
+        self
+    }
+
+    /// Sets whether gzip compressed files are included. A `.gz` suffix is removed from the
+    /// file name before it is matched against the pattern, so `00202.dat.gz` is found as
+    /// `00202.dat`.
+    pub fn compressed(mut self, compressed: bool) -> Self {
+        self.compressed = compressed;
+        self
+    }
+
+    /// Sets whether the entries of `.zip` archives are included. Entries are matched by their
+    /// file name without the folders inside the archive.
+    pub fn archives(mut self, archives: bool) -> Self {
+        self.archives = archives;
+        self
+    }
+
+    /// Returns the pattern file names are matched against.
+    pub fn pattern(&self) -> &str {
+        self.pattern.as_str()
+    }
+
+    /// Returns the key of a file name, or `None` if the name does not match the pattern.
+    pub fn key(&self, file_name: &str) -> Option<KsmFileKey> {
+        let file_name = match file_name.len().checked_sub(3) {
+            Some(end)
+                if self.compressed
+                    && file_name.is_char_boundary(end)
+                    && file_name[end..].eq_ignore_ascii_case(".gz") =>
+            {
+                &file_name[..end]
+            }
+            _ => file_name,
+        };
+        let captures = self.pattern.captures(file_name)?;
+        match captures.name("article") {
+            Some(article) => {
+                let variant = match captures.name("variant") {
+                    Some(variant) => Some(variant.as_str().parse().ok()?),
+                    None => None,
+                };
+                Some(KsmFileKey::new(article.as_str().parse().ok()?, variant))
+            }
+            None => file_name.parse().ok(),
+        }
+    }
+
+    /// Lists the matching files in `dir`, sorted by path and archive entry name.
+    ///
+    /// Compressed files and archive entries hold history, so they can share a key with each
+    /// other and with a current file. Current files sharing a key cannot be told apart, e.g.
+    /// `202.dat` and `00202.dat`, so all of them are left out and listed in
+    /// `DiscoveryResult::failed` with `ParseError::DuplicateFileKey`. Zip archives that cannot
+    /// be read are listed there as well, with `ParseError::ArchiveError`.
+    ///
+    /// # Errors
+    /// * `ParseError::ReadFolderError` if a folder cannot be listed.
+    /// * `ParseError::ReadMetadataError` if the metadata of a file cannot be read.
+    /// * `ParseError::FileNameExtractionError` if a file name is not valid UTF-8.
+    pub fn discover<P: AsRef<Path>>(&self, dir: P) -> Result<DiscoveryResult, ParseError> {
+        let mut files = Vec::new();
+        let mut result = DiscoveryResult::default();
+        self.discover_into(dir.as_ref(), &mut files, &mut result.failed)?;
+        files.sort_by(|a, b| (&a.path, &a.entry).cmp(&(&b.path, &b.entry)));
+
+        let mut current_files: BTreeMap<KsmFileKey, Vec<PathBuf>> = BTreeMap::new();
+        for file in files.iter().filter(|file| !file.is_archived()) {
+            current_files
+                .entry(file.key)
+                .or_default()
+                .push(file.path.clone());
+        }
+        for file in files {
+            match current_files.get(&file.key) {
+                Some(paths) if paths.len() > 1 && !file.is_archived() => {
+                    let others = paths.iter().filter(|path| **path != file.path).cloned();
+                    result.failed.push(DiscoveryFailure {
+                        error: ParseError::DuplicateFileKey {
+                            location: Location::file(&file.path),
+                            key: file.key,
+                            others: others.collect(),
+                        },
+                        path: file.path,
+                        modified: file.modified,
+                    });
+                }
+                _ => result.files.push(file),
+            }
+        }
+        result.failed.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(result)
+    }
+
+    fn discover_into(
+        &self,
+        dir: &Path,
+        files: &mut Vec<DiscoveredFile>,
+        failed: &mut Vec<DiscoveryFailure>,
+    ) -> Result<(), ParseError> {
+        let read_folder_error = |source| ParseError::ReadFolderError {
+            location: Location::file(dir),
+            source,
+        };
+        for entry in fs::read_dir(dir).map_err(read_folder_error)? {
+            let entry = entry.map_err(read_folder_error)?;
+            let path = entry.path();
+            let metadata = entry
+                .metadata()
+                .map_err(|source| ParseError::ReadMetadataError {
+                    location: Location::file(&path),
+                    source,
+                })?;
+
+            if metadata.is_dir() {
+                if self.recursive {
+                    self.discover_into(&path, files, failed)?;
+                }
+                continue;
+            }
+
+            let file_name = match path.file_name().and_then(|name| name.to_str()) {
+                Some(file_name) => file_name.to_owned(),
+                None => {
+                    return Err(ParseError::FileNameExtractionError {
+                        location: Location::file(&path),
+                    })
+                }
+            };
+            let is_archive = path
+                .extension()
+                .is_some_and(|extension| extension.eq_ignore_ascii_case("zip"));
+            if self.archives && is_archive {
+                let modified =
+                    metadata
+                        .modified()
+                        .map_err(|source| ParseError::ReadMetadataError {
+                            location: Location::file(&path),
+                            source,
+                        })?;
+                // A damaged archive only leaves out its own entries
+                let entries = match archive::list_archive(&path, self) {
+                    Ok(entries) => entries,
+                    Err(error) => {
+                        failed.push(DiscoveryFailure {
+                            path,
+                            modified,
+                            error,
+                        });
+                        continue;
+                    }
+                };
+                for entry in entries {
+                    files.push(DiscoveredFile {
+                        key: entry.key,
+                        path: path.clone(),
+                        file_name: entry.file_name().to_string(),
+                        entry: Some(entry.name),
+                        modified,
+                        len: entry.len,
+                    });
+                }
+            } else if let Some(key) = self.key(&file_name) {
+                let modified =
+                    metadata
+                        .modified()
+                        .map_err(|source| ParseError::ReadMetadataError {
+                            location: Location::file(&path),
+                            source,
+                        })?;
+                files.push(DiscoveredFile {
+                    key,
+                    path,
+                    file_name,
+                    entry: None,
+                    modified,
+                    len: metadata.len(),
+                });
+            }
+        }
+        Ok(())
+    }
+}
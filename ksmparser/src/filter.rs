@@ -0,0 +1,400 @@
+/// Module for parsing row filters, e.g. `wall_min < 0.2 and info5 == "Anna"`.
+///
+/// A filter compares columns with literals or other columns and combines the comparisons with
+/// `and`, `or`, `not` and parentheses:
+///
+/// ```text
+/// filter     = or
+/// or         = and ("or" and)*
+/// and        = not ("and" not)*
+/// not        = "not" not | "(" filter ")" | comparison
+/// comparison = column ("==" | "=" | "!=" | "<" | "<=" | ">" | ">=") (literal | column)
+///            | column "is" ["not"] "null"
+/// literal    = number | "string" | 'string' | true | false
+/// ```
+///
+/// Parentheses and `not` can be nested up to `MAX_NESTING` levels deep.
+///
+/// Filters are checked against the schema of the DataFrame they are applied to, so unknown
+/// columns and comparisons of mismatching types are reported before any row is read. Numeric
+/// columns compare with numbers, string columns with strings and boolean columns with `true`
+/// or `false` using `==` and `!=`.
+use super::QueryError;
+use polars::prelude::*;
+
+/// Maximum number of nested parentheses and `not`s, which bounds the recursion of the parser.
+pub const MAX_NESTING: usize = 64;
+
+/// Comparison operators of a filter
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Column(String),
+    Number(String),
+    Str(String),
+    Bool(bool),
+    Operator(Operator),
+    And,
+    Or,
+    Not,
+    Is,
+    Null,
+    Open,
+    Close,
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Token::Column(name) => format!("column {}", name),
+            Token::Number(number) => format!("number {}", number),
+            Token::Str(value) => format!("string \"{}\"", value),
+            Token::Bool(value) => value.to_string(),
+            Token::Operator(_) => "comparison operator".to_string(),
+            Token::And => "and".to_string(),
+            Token::Or => "or".to_string(),
+            Token::Not => "not".to_string(),
+            Token::Is => "is".to_string(),
+            Token::Null => "null".to_string(),
+            Token::Open => "(".to_string(),
+            Token::Close => ")".to_string(),
+        }
+    }
+}
+
+/// Parses a filter into an expression for `LazyFrame::filter`.
+///
+/// # Arguments
+/// * `filter` - The filter, e.g. `wall_min < 0.2 and info5 == "Anna"`.
+/// * `schema` - Schema of the DataFrame the filter is applied to.
+///
+/// # Errors
+/// * `QueryError::InvalidFilter` if the filter is not valid, is nested more than `MAX_NESTING`
+///   levels deep, refers to a column missing from `schema` or compares values of mismatching
+///   types. The error includes the character position of the problem.
+pub fn parse_filter(filter: &str, schema: &Schema) -> Result<Expr, QueryError> {
+    let tokens = tokenize(filter)?;
+    let mut parser = Parser {
+        filter,
+        tokens,
+        index: 0,
+        depth: 0,
+        schema,
+    };
+    let expr = parser.or()?;
+    match parser.tokens.get(parser.index) {
+        Some((position, token)) => Err(invalid(
+            filter,
+            *position,
+            format!("Unexpected {}", token.describe()),
+        )),
+        None => Ok(expr),
+    }
+}
+
+fn invalid(filter: &str, position: usize, reason: String) -> QueryError {
+    QueryError::InvalidFilter {
+        filter: filter.to_string(),
+        position,
+        reason,
+    }
+}
+
+/// Splits a filter into tokens paired with their character positions.
+fn tokenize(filter: &str) -> Result<Vec<(usize, Token)>, QueryError> {
+    let chars: Vec<char> = filter.chars().collect();
+    let mut tokens = Vec::new();
+    let mut index = 0;
+
+    while index < chars.len() {
+        let start = index;
+        let c = chars[index];
+        let token = match c {
+            _ if c.is_whitespace() => {
+                index += 1;
+                continue;
+            }
+            '(' => {
+                index += 1;
+                Token::Open
+            }
+            ')' => {
+                index += 1;
+                Token::Close
+            }
+            '=' | '!' | '<' | '>' => {
+                let next = chars.get(index + 1).copied();
+                let (operator, length) = match (c, next) {
+                    ('=', Some('=')) => (Operator::Eq, 2),
+                    ('=', _) => (Operator::Eq, 1),
+                    ('!', Some('=')) => (Operator::NotEq, 2),
+                    ('<', Some('=')) => (Operator::LtEq, 2),
+                    ('<', _) => (Operator::Lt, 1),
+                    ('>', Some('=')) => (Operator::GtEq, 2),
+                    ('>', _) => (Operator::Gt, 1),
+                    _ => {
+                        return Err(invalid(filter, start, "Expected != after !".to_string()));
+                    }
+                };
+                index += length;
+                Token::Operator(operator)
+            }
+            '"' | '\'' => {
+                let end = chars[index + 1..]
+                    .iter()
+                    .position(|&other| other == c)
+                    .ok_or_else(|| invalid(filter, start, "Unterminated string".to_string()))?;
+                let value: String = chars[index + 1..index + 1 + end].iter().collect();
+                index += end + 2;
+                Token::Str(value)
+            }
+            _ if c.is_ascii_digit() || c == '-' || c == '+' || c == '.' => {
+                let length = chars[index + 1..]
+                    .iter()
+                    .position(|&other| {
+                        !(other.is_ascii_alphanumeric() || matches!(other, '.' | '-' | '+'))
+                    })
+                    .unwrap_or(chars.len() - index - 1)
+                    + 1;
+                let number: String = chars[index..index + length].iter().collect();
+                if number.parse::<f64>().is_err() {
+                    return Err(invalid(filter, start, format!("Invalid number {}", number)));
+                }
+                index += length;
+                Token::Number(number)
+            }
+            _ if c.is_alphabetic() || c == '_' => {
+                let length = chars[index..]
+                    .iter()
+                    .position(|&other| !(other.is_alphanumeric() || other == '_'))
+                    .unwrap_or(chars.len() - index);
+                let word: String = chars[index..index + length].iter().collect();
+                index += length;
+                match word.to_ascii_lowercase().as_str() {
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "not" => Token::Not,
+                    "is" => Token::Is,
+                    "null" => Token::Null,
+                    "true" => Token::Bool(true),
+                    "false" => Token::Bool(false),
+                    _ => Token::Column(word),
+                }
+            }
+            _ => {
+                return Err(invalid(
+                    filter,
+                    start,
+                    format!("Unexpected character {}", c),
+                ));
+            }
+        };
+        tokens.push((start, token));
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent parser over the tokens of a filter
+struct Parser<'a> {
+    filter: &'a str,
+    tokens: Vec<(usize, Token)>,
+    index: usize,
+    /// Number of enclosing parentheses and `not`s
+    depth: usize,
+    schema: &'a Schema,
+}
+
+impl Parser<'_> {
+    /// Returns the next token and its position, or an error at the end of the filter.
+    fn next(&mut self, expected: &str) -> Result<(usize, Token), QueryError> {
+        match self.tokens.get(self.index) {
+            Some(token) => {
+                self.index += 1;
+                Ok(token.clone())
+            }
+            None => Err(invalid(
+                self.filter,
+                self.filter.chars().count(),
+                format!("Expected {} at the end of the filter", expected),
+            )),
+        }
+    }
+
+    /// Consumes the next token if it equals `token`.
+    fn accept(&mut self, token: &Token) -> bool {
+        let matches = matches!(self.tokens.get(self.index), Some((_, next)) if next == token);
+        if matches {
+            self.index += 1;
+        }
+        matches
+    }
+
+    fn or(&mut self) -> Result<Expr, QueryError> {
+        let mut expr = self.and()?;
+        while self.accept(&Token::Or) {
+            expr = expr.or(self.and()?);
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, QueryError> {
+        let mut expr = self.not()?;
+        while self.accept(&Token::And) {
+            expr = expr.and(self.not()?);
+        }
+        Ok(expr)
+    }
+
+    fn not(&mut self) -> Result<Expr, QueryError> {
+        let (position, token) = self.next("a comparison")?;
+        match token {
+            Token::Not => Ok(self.nested(position, Self::not)?.not()),
+            Token::Open => {
+                let expr = self.nested(position, Self::or)?;
+                match self.next(")")? {
+                    (_, Token::Close) => Ok(expr),
+                    (position, token) => Err(invalid(
+                        self.filter,
+                        position,
+                        format!("Expected ) but found {}", token.describe()),
+                    )),
+                }
+            }
+            Token::Column(name) => self.comparison(position, name),
+            token => Err(invalid(
+                self.filter,
+                position,
+                format!("Expected a column but found {}", token.describe()),
+            )),
+        }
+    }
+
+    /// Parses one level deeper with `parse`, or returns an error at `position` if the filter is
+    /// nested too deeply.
+    fn nested(
+        &mut self,
+        position: usize,
+        parse: fn(&mut Self) -> Result<Expr, QueryError>,
+    ) -> Result<Expr, QueryError> {
+        if self.depth == MAX_NESTING {
+            return Err(invalid(
+                self.filter,
+                position,
+                format!("Filter is nested more than {} levels deep", MAX_NESTING),
+            ));
+        }
+        self.depth += 1;
+        let expr = parse(self);
+        self.depth -= 1;
+        expr
+    }
+
+    /// Returns the data type of a column, or an error naming the unknown column.
+    fn dtype(&self, position: usize, name: &str) -> Result<&DataType, QueryError> {
+        self.schema
+            .get(name)
+            .ok_or_else(|| invalid(self.filter, position, format!("Unknown column {}", name)))
+    }
+
+    fn comparison(&mut self, position: usize, name: String) -> Result<Expr, QueryError> {
+        let dtype = self.dtype(position, &name)?.clone();
+        let column = col(name.as_str());
+
+        let (operator_position, operator) = match self.next("a comparison operator")? {
+            (_, Token::Is) => {
+                let negated = self.accept(&Token::Not);
+                return match self.next("null")? {
+                    (_, Token::Null) if negated => Ok(column.is_not_null()),
+                    (_, Token::Null) => Ok(column.is_null()),
+                    (position, token) => Err(invalid(
+                        self.filter,
+                        position,
+                        format!("Expected null but found {}", token.describe()),
+                    )),
+                };
+            }
+            (position, Token::Operator(operator)) => (position, operator),
+            (position, token) => {
+                return Err(invalid(
+                    self.filter,
+                    position,
+                    format!(
+                        "Expected a comparison operator but found {}",
+                        token.describe()
+                    ),
+                ))
+            }
+        };
+
+        let (value_position, value) = self.next("a value")?;
+        let mismatch = |found: &str| {
+            invalid(
+                self.filter,
+                value_position,
+                format!(
+                    "Column {} of type {} cannot be compared with {}",
+                    name, dtype, found
+                ),
+            )
+        };
+        let value = match value {
+            Token::Number(number) if dtype.is_primitive_numeric() => match number.parse::<i64>() {
+                Ok(integer) if dtype.is_integer() => lit(integer),
+                _ => lit(number.parse::<f64>().unwrap_or(f64::NAN)),
+            },
+            Token::Str(value) if dtype.is_string() => lit(value),
+            Token::Bool(value) if dtype.is_bool() => {
+                if !matches!(operator, Operator::Eq | Operator::NotEq) {
+                    return Err(invalid(
+                        self.filter,
+                        operator_position,
+                        format!("Boolean column {} only supports == and !=", name),
+                    ));
+                }
+                lit(value)
+            }
+            Token::Column(other) => {
+                let other_dtype = self.dtype(value_position, &other)?;
+                let comparable = (dtype.is_primitive_numeric()
+                    && other_dtype.is_primitive_numeric())
+                    || (dtype.is_string() && other_dtype.is_string())
+                    || (dtype.is_bool() && other_dtype.is_bool());
+                if !comparable {
+                    return Err(mismatch(&format!(
+                        "column {} of type {}",
+                        other, other_dtype
+                    )));
+                }
+                col(other.as_str())
+            }
+            token @ (Token::Number(_) | Token::Str(_) | Token::Bool(_)) => {
+                return Err(mismatch(&token.describe()))
+            }
+            token => {
+                return Err(invalid(
+                    self.filter,
+                    value_position,
+                    format!("Expected a value but found {}", token.describe()),
+                ))
+            }
+        };
+
+        Ok(match operator {
+            Operator::Eq => column.eq(value),
+            Operator::NotEq => column.neq(value),
+            Operator::Lt => column.lt(value),
+            Operator::LtEq => column.lt_eq(value),
+            Operator::Gt => column.gt(value),
+            Operator::GtEq => column.gt_eq(value),
+        })
+    }
+}
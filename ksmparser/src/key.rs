@@ -0,0 +1,97 @@
+/// Module for identifying KSM files by article number and variant.
+///
+/// KSM files are named after the article they belong to, e.g. `00202.dat`, with an optional
+/// `-N` suffix for variants of the same article, e.g. `00202-1.dat`.
+use super::ParseError;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Identifies the KSM files of an article variant.
+///
+/// Keys order by article number first, and the article without a variant suffix comes before
+/// its variants. They are displayed as the zero padded file stem, e.g. `00202-1`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct KsmFileKey {
+    /// Article number
+    pub article: u32,
+    /// Variant number from the `-N` suffix
+    pub variant: Option<u8>,
+}
+
+impl KsmFileKey {
+    /// Creates a key for the given article and variant.
+    pub fn new(article: u32, variant: Option<u8>) -> Self {
+        KsmFileKey { article, variant }
+    }
+
+    /// Parses a file stem of 1 to 5 digits with an optional single digit variant suffix.
+    fn from_stem(stem: &str) -> Option<Self> {
+        let (article, variant) = match stem.split_once('-') {
+            Some((article, variant)) => (article, Some(variant)),
+            None => (stem, None),
+        };
+        let is_number = |digits: &str, min: usize, max: usize| {
+            (min..=max).contains(&digits.len()) && digits.bytes().all(|b| b.is_ascii_digit())
+        };
+
+        if !is_number(article, 1, 5) {
+            return None;
+        }
+        let variant = match variant {
+            Some(variant) if is_number(variant, 1, 1) => Some(variant.parse().ok()?),
+            Some(_) => return None,
+            None => None,
+        };
+        Some(KsmFileKey {
+            article: article.parse().ok()?,
+            variant,
+        })
+    }
+
+    /// Returns the file name of the key with the given extension, e.g. `00202-1.dat`.
+    pub fn file_name(&self, file_extension: &str) -> String {
+        format!("{}.{}", self, file_extension)
+    }
+}
+
+impl FromStr for KsmFileKey {
+    type Err = ParseError;
+
+    /// Parses a key with or without zero padding, variant suffix and file extension, so that
+    /// `202`, `00202-1` and `00202-1.dat` are all accepted.
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let stem = match name.rsplit_once('.') {
+            Some((stem, extension)) if extension.bytes().all(|b| b.is_ascii_alphabetic()) => stem,
+            _ => name,
+        };
+        KsmFileKey::from_stem(stem).ok_or_else(|| ParseError::InvalidFileKey {
+            name: name.to_string(),
+        })
+    }
+}
+
+impl TryFrom<String> for KsmFileKey {
+    type Error = ParseError;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        name.parse()
+    }
+}
+
+impl From<KsmFileKey> for String {
+    fn from(key: KsmFileKey) -> Self {
+        key.to_string()
+    }
+}
+
+impl fmt::Display for KsmFileKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:05}", self.article)?;
+        if let Some(variant) = self.variant {
+            write!(f, "-{}", variant)?;
+        }
+        Ok(())
+    }
+}
@@ -1,158 +1,920 @@
+pub mod aggregate;
+pub mod archive;
 pub mod article;
+pub mod checkresult;
+pub mod compliance;
+pub mod discovery;
+pub mod filter;
+pub mod key;
 pub mod measurement;
-use encoding_rs::ISO_8859_10;
+pub mod schema;
+pub mod statistics;
+use discovery::Discovery;
+use encoding_rs::{Encoding, ISO_8859_10, UTF_8};
 use encoding_rs_io::DecodeReaderBytesBuilder;
-use polars::prelude::DataFrame;
+use flate2::bufread::MultiGzDecoder;
+use key::KsmFileKey;
+use polars::chunked_array::temporal::validate_time_zone;
+use polars::prelude::{Column, DataFrame, DataType, PlSmallStr, PolarsError, PolarsResult};
+use rayon::prelude::*;
+use schema::ColumnSchema;
 use std::collections::HashMap;
+use std::error::Error;
 use std::fmt;
-use std::fs::{self, File};
-use std::io::{self, BufRead, BufReader};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Cursor, Read, Write};
 use std::path::{Path, PathBuf};
-use regex::Regex;
+use std::str::FromStr;
+use std::sync::Arc;
+use zip::result::ZipError;
 
-/// Reads lines from a given file and decodes them using the ISO_8859_10 encoding.
+/// Text encoding used when decoding KSM files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextEncoding {
+    /// Decode with the given encoding. A byte order mark still takes precedence.
+    Fixed(&'static Encoding),
+    /// Detect the encoding from a byte order mark, otherwise use UTF-8 if the content is valid
+    /// UTF-8 and fall back to ISO_8859_10.
+    Auto,
+}
+
+impl Default for TextEncoding {
+    /// ISO_8859_10, which is what the gauges write by default
+    fn default() -> Self {
+        TextEncoding::Fixed(ISO_8859_10)
+    }
+}
+
+impl FromStr for TextEncoding {
+    type Err = ParseError;
+
+    /// Parses `auto` or any WHATWG encoding label such as `utf-8`, `iso-8859-10` or `windows-1252`.
+    fn from_str(label: &str) -> Result<Self, Self::Err> {
+        if label.trim().eq_ignore_ascii_case("auto") {
+            return Ok(TextEncoding::Auto);
+        }
+        match Encoding::for_label(label.trim().as_bytes()) {
+            Some(encoding) => Ok(TextEncoding::Fixed(encoding)),
+            None => Err(ParseError::UnknownEncoding {
+                label: label.to_string(),
+            }),
+        }
+    }
+}
+
+impl TextEncoding {
+    /// Detects the encoding of `bytes` according to the rules of `TextEncoding::Auto`.
+    fn detect(bytes: &[u8]) -> &'static Encoding {
+        if let Some((encoding, _)) = Encoding::for_bom(bytes) {
+            return encoding;
+        }
+        match std::str::from_utf8(bytes) {
+            Ok(_) => UTF_8,
+            Err(_) => ISO_8859_10,
+        }
+    }
+}
+
+/// Time zone of the `local_time` column derived from the measurement time.
+///
+/// Accepts names from the time zone database such as `Europe/Stockholm`. The default is UTC.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Timezone(PlSmallStr);
+
+impl Default for Timezone {
+    fn default() -> Self {
+        Timezone(PlSmallStr::from_static("UTC"))
+    }
+}
+
+impl FromStr for Timezone {
+    type Err = ParseError;
+
+    /// Parses a time zone name, rejecting names that are not in the time zone database.
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match validate_time_zone(name.trim()) {
+            Ok(()) => Ok(Timezone(PlSmallStr::from_str(name.trim()))),
+            Err(_) => Err(ParseError::UnknownTimezone {
+                name: name.to_string(),
+            }),
+        }
+    }
+}
+
+impl Timezone {
+    /// Returns the name of the time zone.
+    pub fn name(&self) -> &str {
+        self.0.as_str()
+    }
+}
+
+impl fmt::Display for Timezone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// How a column name that occurs more than once in a measurement entry is handled.
+///
+/// Every duplicate is recorded in the `ParseReport` with the line of the entry.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DuplicatePolicy {
+    /// Fail the file, or leave the whole entry out of the result when parsing leniently
+    #[default]
+    Reject,
+    /// Keep the value of the first occurrence and discard the others
+    KeepFirst,
+    /// Keep the value of the last occurrence and discard the others
+    KeepLast,
+    /// Keep every value, renaming repeated columns with a numbered suffix, e.g. `wall_min_2`
+    Rename,
+}
+
+impl FromStr for DuplicatePolicy {
+    type Err = ParseError;
+
+    /// Parses `reject`, `first`, `last` or `rename`, ignoring case.
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "reject" => Ok(DuplicatePolicy::Reject),
+            "first" => Ok(DuplicatePolicy::KeepFirst),
+            "last" => Ok(DuplicatePolicy::KeepLast),
+            "rename" => Ok(DuplicatePolicy::Rename),
+            _ => Err(ParseError::UnknownDuplicatePolicy {
+                name: name.to_string(),
+            }),
+        }
+    }
+}
+
+/// Options shared by the article and measurement parsers.
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+    /// Text encoding of the parsed files
+    pub encoding: TextEncoding,
+    /// Skip malformed measurement entries instead of failing the whole file.
+    /// Skipped entries are listed in the `ParseReport`. Lenient parsing also reads past empty
+    /// lines and detects entries whose value row is missing, where strict parsing stops at the
+    /// first empty line and pairs the lines by position.
+    pub lenient: bool,
+    /// Types and names of the measurement columns
+    pub schema: Arc<ColumnSchema>,
+    /// Time zone of the `local_time` column
+    pub timezone: Timezone,
+    /// Handling of column names that occur more than once in a measurement entry
+    pub duplicates: DuplicatePolicy,
+}
+
+/// What the parser did about a problem in the input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IssueAction {
+    /// The entry was left out of the result
+    Skipped,
+    /// A value could not be converted to the column's data type and was stored as null
+    Coerced,
+    /// The value of a duplicate column was discarded
+    Discarded,
+    /// A duplicate column was stored under a new name
+    Renamed,
+    /// A column was converted to a common data type to align it with another DataFrame
+    Promoted,
+    /// A column kept its name because the new name given by the schema was already taken
+    Kept,
+}
+
+/// A problem in the input that was worked around instead of failing the parse.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseIssue {
+    pub location: Location,
+    pub action: IssueAction,
+    pub reason: String,
+}
+
+impl fmt::Display for ParseIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let action = match self.action {
+            IssueAction::Skipped => "Skipped entry",
+            IssueAction::Coerced => "Coerced value to null",
+            IssueAction::Discarded => "Discarded value",
+            IssueAction::Renamed => "Renamed column",
+            IssueAction::Promoted => "Promoted column",
+            IssueAction::Kept => "Kept column name",
+        };
+        // Issues that do not relate to a position, such as promotions, have no location
+        if self.location.path.is_some() || self.location.line.is_some() {
+            write!(f, "{}: ", self.location)?;
+        }
+        write!(f, "{}: {}", action, self.reason)
+    }
+}
+
+/// Diagnostics collected while parsing a file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ParseReport {
+    /// Every skipped or coerced entry, in the order they were found
+    pub issues: Vec<ParseIssue>,
+}
+
+impl ParseReport {
+    /// Returns true if the input was parsed without any issues.
+    pub fn is_empty(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    /// Records an issue found at the given line.
+    pub(crate) fn push(&mut self, line: usize, action: IssueAction, reason: String) {
+        self.issues.push(ParseIssue {
+            location: Location::line(line),
+            action,
+            reason,
+        });
+    }
+
+    /// Sets the path of all issues that are not yet associated with a file.
+    pub(crate) fn with_path<P: AsRef<Path>>(mut self, path: P) -> Self {
+        for issue in self.issues.iter_mut() {
+            if issue.location.path.is_none() {
+                issue.location.path = Some(path.as_ref().to_path_buf());
+            }
+        }
+        self
+    }
+}
+
+/// Returns true if the file at `path` is gzip compressed, judging by its `.gz` extension.
+fn is_gzip<P: AsRef<Path>>(path: P) -> bool {
+    path.as_ref()
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("gz"))
+}
+
+/// Opens a file for reading, decompressing it if it is gzip compressed.
+fn open_file<P: AsRef<Path>>(file_path: P) -> io::Result<Box<dyn Read + Send>> {
+    let file = BufReader::new(File::open(&file_path)?);
+    if is_gzip(&file_path) {
+        Ok(Box::new(MultiGzDecoder::new(file)))
+    } else {
+        Ok(Box::new(file))
+    }
+}
+
+/// Reads lines from a given file and decodes them according to `options`.
 ///
 /// The function opens a file specified by the `file_path` and decodes its content
-/// from ISO_8859_10 to UTF-8, returning an iterator over the resulting lines.
+/// to UTF-8, returning an iterator over the resulting lines. Files with a `.gz` extension
+/// are decompressed first.
 /// Each line is wrapped in a `Result` to handle potential errors in reading or decoding.
-fn read_and_decode_lines<P: AsRef<Path>>(
+fn read_and_decode_lines<'a, P: AsRef<Path>>(
     file_path: P,
-) -> io::Result<impl Iterator<Item = io::Result<String>>> {
-    let file = File::open(file_path)?;
-    let reader = BufReader::new(file);
+    options: &ParseOptions,
+) -> io::Result<impl Iterator<Item = io::Result<String>> + 'a> {
+    decode_lines(BufReader::new(open_file(file_path)?), options)
+}
+
+/// Decodes the bytes produced by `reader` according to `options` and returns an iterator over the lines.
+///
+/// With `TextEncoding::Auto` the whole input is read before decoding, since the detection
+/// has to look at all of it.
+fn decode_lines<'a, R: Read + 'a>(
+    mut reader: R,
+    options: &ParseOptions,
+) -> io::Result<impl Iterator<Item = io::Result<String>> + 'a> {
+    let (encoding, reader): (_, Box<dyn Read + 'a>) = match options.encoding {
+        TextEncoding::Fixed(encoding) => (encoding, Box::new(reader)),
+        TextEncoding::Auto => {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes)?;
+            (TextEncoding::detect(&bytes), Box::new(Cursor::new(bytes)))
+        }
+    };
 
-    // Create a decoder that handles ISO_8859_10 encoding
+    // Create a decoder that handles the chosen encoding
     let decoder = DecodeReaderBytesBuilder::new()
-        .encoding(Some(ISO_8859_10))
+        .encoding(Some(encoding))
         .build(reader);
     Ok(BufReader::new(decoder).lines())
 }
 
+/// Formats the values of a column the way they are written to KSM files.
+///
+/// Floats are written with the shortest representation that parses back to the same value.
+/// Other data types are cast to strings.
+fn format_column(column: &Column) -> PolarsResult<Vec<Option<String>>> {
+    fn format<T: fmt::Display>(values: impl Iterator<Item = Option<T>>) -> Vec<Option<String>> {
+        values.map(|value| value.map(|v| v.to_string())).collect()
+    }
+
+    let series = column.as_materialized_series();
+    Ok(match series.dtype() {
+        DataType::Float64 => format(series.f64()?.iter()),
+        DataType::Float32 => format(series.f32()?.iter()),
+        _ => format(series.cast(&DataType::String)?.str()?.iter()),
+    })
+}
+
+/// Checks that `field` can be written to a KSM file and read back unchanged.
+///
+/// Fields may not contain line breaks, any of `separators` or characters that ISO-8859-10 cannot
+/// represent. Fields that the parser trims must not have surrounding whitespace.
+fn check_field(
+    field: &str,
+    column: &str,
+    line: usize,
+    separators: &[&str],
+    trimmed: bool,
+) -> Result<(), ParseError> {
+    let reason = if field.contains(['\n', '\r']) {
+        Some(String::from("contains a line break"))
+    } else if let Some(separator) = separators.iter().find(|s| field.contains(**s)) {
+        Some(format!("contains the separator {:?}", separator))
+    } else if trimmed && field.trim() != field {
+        Some(String::from("has surrounding whitespace"))
+    } else if ISO_8859_10.encode(field).2 {
+        Some(String::from("is not representable in ISO-8859-10"))
+    } else {
+        None
+    };
+
+    match reason {
+        Some(reason) => Err(ParseError::UnwritableValue {
+            location: Location::line(line),
+            column: column.to_string(),
+            value: field.to_string(),
+            reason,
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Writes `line` encoded in ISO-8859-10 followed by a newline.
+///
+/// The line is expected to consist of fields that passed `check_field`.
+fn write_line<W: Write>(writer: &mut W, line: &str, line_number: usize) -> Result<(), ParseError> {
+    let (bytes, _, _) = ISO_8859_10.encode(line);
+    writer
+        .write_all(&bytes)
+        .and_then(|_| writer.write_all(b"\n"))
+        .map_err(|source| ParseError::WriteError {
+            location: Location::line(line_number),
+            source,
+        })
+}
+
+/// Outcome of parsing every file in a folder.
+#[derive(Debug, Default)]
+pub struct FolderParseResult {
+    /// Parsed files keyed by article number and variant
+    pub parsed: HashMap<KsmFileKey, DataFrame>,
+    /// Files that could not be parsed, keyed by file name
+    pub failed: HashMap<String, ParseError>,
+}
+
+impl FolderParseResult {
+    /// Returns the parsed files, or the error of the first failed file in file name order.
+    pub fn into_result(self) -> Result<HashMap<KsmFileKey, DataFrame>, ParseError> {
+        match self.failed.into_iter().min_by(|a, b| a.0.cmp(&b.0)) {
+            Some((_, error)) => Err(error),
+            None => Ok(self.parsed),
+        }
+    }
+}
+
+/// Parses the files in `dir` on the rayon thread pool, collecting a result for every file.
+///
+/// Only listing the folder can fail as a whole. Errors in individual files are collected in
+/// `FolderParseResult::failed` without affecting the other files.
 fn parse_folder<P: AsRef<Path>>(
     dir: P,
-    parse_function: fn(file_path: PathBuf) -> Result<DataFrame, ParseError>,
+    parse_function: fn(file_path: PathBuf, options: &ParseOptions) -> Result<DataFrame, ParseError>,
     file_extension: &str,
-) -> Result<HashMap<String, DataFrame>, ParseError> {
-    let mut map: HashMap<String, DataFrame> = HashMap::new();
+    options: &ParseOptions,
+) -> Result<FolderParseResult, ParseError> {
+    //Parse the files named after an article number with the right extension
+    let discovered = Discovery::new(file_extension)?.discover(dir)?;
+    let results: Vec<_> = discovered
+        .files
+        .into_par_iter()
+        .map(|file| {
+            let result = parse_function(file.path, options);
+            (file.key, file.file_name, result)
+        })
+        .collect();
 
-    //Compile regex pattern for filename
-    let pattern_string = format!(r"^\d{{3,5}}(-\d)?{}$", regex::escape(file_extension));
-    let filename_pattern = Regex::new(&pattern_string).map_err(|_| ParseError::InvalidRegex)?;
+    let mut folder = FolderParseResult::default();
+    for failure in discovered.failed {
+        let file_name = failure.path.file_name().unwrap_or_default();
+        folder
+            .failed
+            .insert(file_name.to_string_lossy().into_owned(), failure.error);
+    }
+    for (key, file_name, result) in results {
+        match result {
+            Ok(data_frame) => {
+                folder.parsed.insert(key, data_frame);
+            }
+            Err(error) => {
+                folder.failed.insert(file_name, error);
+            }
+        }
+    }
+    Ok(folder)
+}
 
-    //Iterate files in directory
-    for entry in fs::read_dir(dir).map_err(|_| ParseError::ReadFolderError)? {
-        let path = entry.map_err(|_| ParseError::ReadFolderError)?.path();
+/// Position in the parsed input where a `ParseError` occurred.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Location {
+    /// Path of the file or folder being parsed. `None` when parsing from a reader.
+    pub path: Option<PathBuf>,
+    /// 1-based line number. `None` when the error does not relate to a single line.
+    pub line: Option<usize>,
+}
 
-        if let Some(file_name) = path.file_name().and_then(|name| name.to_str()) {
-            //Check if filename matches pattern
-            if filename_pattern.is_match(file_name) {
-                let data_frame = parse_function(path.clone())?;
-                map.insert(file_name.to_owned(), data_frame);
-            }
-        } else {
-            return Err(ParseError::FileNameExtractionError);
+impl Location {
+    /// Location referring to a whole file or folder.
+    pub fn file<P: AsRef<Path>>(path: P) -> Self {
+        Location {
+            path: Some(path.as_ref().to_path_buf()),
+            line: None,
+        }
+    }
+
+    /// Location referring to a line in input that is not yet associated with a file.
+    pub fn line(line: usize) -> Self {
+        Location {
+            path: None,
+            line: Some(line),
+        }
+    }
+}
+
+impl fmt::Display for Location {
+    /// Formats the location as `path:line`, leaving out the parts that are unknown.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.path, self.line) {
+            (Some(path), Some(line)) => write!(f, "{}:{}", path.display(), line),
+            (Some(path), None) => write!(f, "{}", path.display()),
+            (None, Some(line)) => write!(f, "line {}", line),
+            (None, None) => Ok(()),
         }
     }
-    Ok(map)
 }
 
 // Define error types for parsing ksm data that can be displayed and formatted
 #[derive(Debug)]
 pub enum ParseError {
-    /// Error for a non-readable file
-    /// Includes the filename
-    InvalidFile(String),
+    /// Error for a file that could not be opened
+    InvalidFile {
+        location: Location,
+        source: io::Error,
+    },
 
     /// Specifies that a required field is missing from the input.
     /// Includes the name of the missing field.
-    MissingField(String),
+    MissingField { location: Location, field: String },
 
     /// Specifies that an entry could not be parsed correctly.
-    /// Includes the line with the malformed entry
-    MalformedEntry(String),
+    /// Includes a description of what is wrong with the entry
+    MalformedEntry { location: Location, reason: String },
+
+    /// Specifies that an error happened while reading the input
+    IOError {
+        location: Location,
+        source: io::Error,
+    },
+
+    /// Specifies that the parsed values could not be turned into a DataFrame
+    DataFrameCreationError {
+        location: Location,
+        source: PolarsError,
+    },
+
+    /// Specifies that a parsed value could not be added as a column.
+    /// Includes the name of the column
+    ColumnCreationError {
+        location: Location,
+        column: String,
+        source: PolarsError,
+    },
 
-    /// Specifies an unexpected error from the internal functions
-    /// Includes a description of where the error happened
-    GeneralError(String),
+    /// Specifies that two DataFrames could not be aligned and stacked
+    DataAlignmentError {
+        location: Location,
+        source: PolarsError,
+    },
 
-    /// Specifies that an error happened while reading a file
-    /// Includes the error message from std::io
-    IOError(String),
+    /// Specifies that the entries of a folder could not be listed
+    ReadFolderError {
+        location: Location,
+        source: io::Error,
+    },
 
-    DataFrameCreationError,
+    /// Specifies that the metadata of a file could not be read
+    ReadMetadataError {
+        location: Location,
+        source: io::Error,
+    },
 
-    ColumnCreationError,
+    /// Specifies that a file name is not valid UTF-8
+    FileNameExtractionError { location: Location },
 
-    DataAlignmentError,
+    /// Specifies that a value could not be converted to the data type of its column
+    TypeConversionError {
+        location: Location,
+        column: String,
+        value: String,
+        dtype: String,
+    },
 
-    ReadFolderError,
+    /// Specifies that a column name occurs more than once in an entry.
+    /// Includes the duplicated column name
+    DuplicateColumns { location: Location, column: String },
 
-    ReadMetadataError,
+    /// Specifies that a file name pattern is not a valid regular expression
+    InvalidRegex {
+        pattern: String,
+        source: regex::Error,
+    },
 
-    FileNameExtractionError,
+    /// Specifies that the epoch column could not be converted to local time
+    EpochToDatetime {
+        location: Location,
+        source: PolarsError,
+    },
 
-    TypeConversionError(String, String, String),
+    /// Specifies that an encoding label is not recognized.
+    /// Includes the label
+    UnknownEncoding { label: String },
 
-    ColumnMismatchError,
+    /// Specifies that a name is not an article number with an optional variant.
+    /// Includes the name
+    InvalidFileKey { name: String },
 
-    DuplicateColumns,
+    /// Specifies that a time zone name is not recognized.
+    /// Includes the name
+    UnknownTimezone { name: String },
 
-    InvalidRegex,
+    /// Specifies that a duplicate column policy is not recognized.
+    /// Includes the name
+    UnknownDuplicatePolicy { name: String },
 
-    EpochToDatetime(String),
+    /// Specifies that a column schema could not be loaded.
+    /// Includes a description of what is wrong with the schema
+    InvalidSchema { location: Location, reason: String },
+
+    /// Specifies that an error happened while writing the output
+    WriteError {
+        location: Location,
+        source: io::Error,
+    },
+
+    /// Specifies that a zip archive or one of its entries could not be read
+    ArchiveError {
+        location: Location,
+        source: ZipError,
+    },
+
+    /// Specifies that a value cannot be represented in the KSM file format.
+    /// Includes the column, the value and why it cannot be written
+    UnwritableValue {
+        location: Location,
+        column: String,
+        value: String,
+        reason: String,
+    },
+
+    /// Specifies that other files have the same article number and variant, e.g. `202.dat` and
+    /// `00202.dat`. Includes the key and the paths of the other files
+    DuplicateFileKey {
+        location: Location,
+        key: KsmFileKey,
+        others: Vec<PathBuf>,
+    },
+}
+
+impl ParseError {
+    /// Returns where in the input the error occurred, if it relates to parsed input.
+    pub fn location(&self) -> Option<&Location> {
+        match self {
+            ParseError::InvalidFile { location, .. }
+            | ParseError::MissingField { location, .. }
+            | ParseError::MalformedEntry { location, .. }
+            | ParseError::IOError { location, .. }
+            | ParseError::DataFrameCreationError { location, .. }
+            | ParseError::ColumnCreationError { location, .. }
+            | ParseError::DataAlignmentError { location, .. }
+            | ParseError::ReadFolderError { location, .. }
+            | ParseError::ReadMetadataError { location, .. }
+            | ParseError::FileNameExtractionError { location }
+            | ParseError::TypeConversionError { location, .. }
+            | ParseError::DuplicateColumns { location, .. }
+            | ParseError::EpochToDatetime { location, .. }
+            | ParseError::WriteError { location, .. }
+            | ParseError::UnwritableValue { location, .. }
+            | ParseError::ArchiveError { location, .. }
+            | ParseError::DuplicateFileKey { location, .. }
+            | ParseError::InvalidSchema { location, .. } => Some(location),
+            ParseError::InvalidRegex { .. }
+            | ParseError::UnknownEncoding { .. }
+            | ParseError::UnknownTimezone { .. }
+            | ParseError::UnknownDuplicatePolicy { .. }
+            | ParseError::InvalidFileKey { .. } => None,
+        }
+    }
+
+    fn location_mut(&mut self) -> Option<&mut Location> {
+        match self {
+            ParseError::InvalidFile { location, .. }
+            | ParseError::MissingField { location, .. }
+            | ParseError::MalformedEntry { location, .. }
+            | ParseError::IOError { location, .. }
+            | ParseError::DataFrameCreationError { location, .. }
+            | ParseError::ColumnCreationError { location, .. }
+            | ParseError::DataAlignmentError { location, .. }
+            | ParseError::ReadFolderError { location, .. }
+            | ParseError::ReadMetadataError { location, .. }
+            | ParseError::FileNameExtractionError { location }
+            | ParseError::TypeConversionError { location, .. }
+            | ParseError::DuplicateColumns { location, .. }
+            | ParseError::EpochToDatetime { location, .. }
+            | ParseError::WriteError { location, .. }
+            | ParseError::UnwritableValue { location, .. }
+            | ParseError::ArchiveError { location, .. }
+            | ParseError::DuplicateFileKey { location, .. }
+            | ParseError::InvalidSchema { location, .. } => Some(location),
+            ParseError::InvalidRegex { .. }
+            | ParseError::UnknownEncoding { .. }
+            | ParseError::UnknownTimezone { .. }
+            | ParseError::UnknownDuplicatePolicy { .. }
+            | ParseError::InvalidFileKey { .. } => None,
+        }
+    }
+
+    /// Sets the path of the error's location unless it is already known.
+    ///
+    /// Used by the functions parsing a file to attach the file to errors raised while
+    /// parsing its lines.
+    pub(crate) fn with_path<P: AsRef<Path>>(mut self, path: P) -> Self {
+        if let Some(location) = self.location_mut() {
+            if location.path.is_none() {
+                location.path = Some(path.as_ref().to_path_buf());
+            }
+        }
+        self
+    }
+
+    /// Sets the line of the error's location unless it is already known.
+    pub(crate) fn at_line(mut self, line: usize) -> Self {
+        if let Some(location) = self.location_mut() {
+            if location.line.is_none() {
+                location.line = Some(line);
+            }
+        }
+        self
+    }
 }
 
 impl fmt::Display for ParseError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Prefix the message with the location, e.g. "00202.dat:12: ". The message of the
+        // wrapped error is left to `source()`, and only appended by the alternate format `{:#}`
+        // for output that does not walk the chain itself, such as log lines.
+        if let Some(location) = self.location() {
+            if location.path.is_some() || location.line.is_some() {
+                write!(f, "{}: ", location)?;
+            }
+        }
+
         match self {
-            ParseError::InvalidFile(filename) => {
-                write!(f, "Invalid file encountered: {}", filename)
+            ParseError::InvalidFile { .. } => {
+                write!(f, "Invalid file encountered")
             }
-            ParseError::MissingField(field) => {
+            ParseError::MissingField { field, .. } => {
                 write!(f, "Missing required field: {}", field)
             }
-            ParseError::MalformedEntry(line) => {
-                write!(f, "Malformed entry found: {}", line)
+            ParseError::MalformedEntry { reason, .. } => {
+                write!(f, "Malformed entry found: {}", reason)
+            }
+            ParseError::IOError { .. } => {
+                write!(f, "Error when reading a file")
+            }
+            ParseError::DataFrameCreationError { .. } => {
+                write!(f, "Failed to create DataFrame")
+            }
+            ParseError::ColumnCreationError { column, .. } => {
+                write!(f, "Failed to create column {}", column)
+            }
+            ParseError::DataAlignmentError { .. } => {
+                write!(f, "Failed to align or insert rows")
+            }
+            ParseError::ReadFolderError { .. } => {
+                write!(f, "Error when iterating entries in a folder")
+            }
+            ParseError::ReadMetadataError { .. } => {
+                write!(f, "Error when reading metadata")
+            }
+            ParseError::FileNameExtractionError { .. } => {
+                write!(f, "Error when extracting file name")
+            }
+            ParseError::TypeConversionError {
+                column,
+                value,
+                dtype,
+                ..
+            } => {
+                write!(
+                    f,
+                    "Error when converting column {} to {}: {}",
+                    column, dtype, value
+                )
+            }
+            ParseError::DuplicateColumns { column, .. } => {
+                write!(f, "Duplicate column: {}", column)
             }
-            ParseError::GeneralError(line) => {
-                write!(f, "General error from ksmparser: {}", line)
+            ParseError::InvalidRegex { pattern, .. } => {
+                write!(f, "Invalid regex {}", pattern)
             }
-            ParseError::IOError(line) => {
-                write!(f, "Error when reading a file: {}", line)
+            ParseError::EpochToDatetime { .. } => {
+                write!(f, "Error converting epoch column to datetime")
             }
-            ParseError::DataFrameCreationError => {
-                write!(f, "Failed to create new row")
+            ParseError::UnknownEncoding { label } => {
+                write!(f, "Unknown text encoding: {}", label)
             }
-            ParseError::ColumnCreationError => {
-                write!(f, "Failed to create new column")
+            ParseError::UnknownTimezone { name } => {
+                write!(f, "Unknown time zone: {}", name)
             }
-            ParseError::DataAlignmentError => {
-                write!(f, "Failed to align or insert row")
+            ParseError::UnknownDuplicatePolicy { name } => {
+                write!(f, "Unknown duplicate column policy: {}", name)
             }
-            ParseError::ReadFolderError => {
-                write!(f, "Error when interating entries in a folder")
+            ParseError::InvalidFileKey { name } => {
+                write!(f, "Not an article number: {}", name)
             }
-            ParseError::ReadMetadataError => {
-                write!(f, "Error when reading metadata for DirEntry")
+            ParseError::InvalidSchema { reason, .. } => {
+                write!(f, "Invalid column schema: {}", reason)
             }
-            ParseError::FileNameExtractionError => {
-                write!(f, "Error when extracting file path")
+            ParseError::WriteError { .. } => {
+                write!(f, "Error when writing a file")
             }
-            ParseError::TypeConversionError(column, value, dtype) => {
-                write!(f, "Error when converting column {} to {}: {}", column, dtype, value)
+            ParseError::ArchiveError { .. } => {
+                write!(f, "Error when reading an archive")
+            }
+            ParseError::UnwritableValue {
+                column,
+                value,
+                reason,
+                ..
+            } => {
+                write!(
+                    f,
+                    "Cannot write '{}' in column {}: {}",
+                    value, column, reason
+                )
+            }
+            ParseError::DuplicateFileKey { key, others, .. } => {
+                let others: Vec<String> = others
+                    .iter()
+                    .map(|path| path.display().to_string())
+                    .collect();
+                write!(
+                    f,
+                    "Article number and variant {} are also used by {}",
+                    key,
+                    others.join(", ")
+                )
+            }
+        }?;
+
+        if f.alternate() {
+            let mut source = self.source();
+            while let Some(error) = source {
+                write!(f, ": {}", error)?;
+                source = error.source();
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Error for ParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ParseError::InvalidFile { source, .. }
+            | ParseError::IOError { source, .. }
+            | ParseError::WriteError { source, .. }
+            | ParseError::ReadFolderError { source, .. }
+            | ParseError::ReadMetadataError { source, .. } => Some(source),
+            ParseError::DataFrameCreationError { source, .. }
+            | ParseError::ColumnCreationError { source, .. }
+            | ParseError::DataAlignmentError { source, .. }
+            | ParseError::EpochToDatetime { source, .. } => Some(source),
+            ParseError::InvalidRegex { source, .. } => Some(source),
+            ParseError::ArchiveError { source, .. } => Some(source),
+            ParseError::MissingField { .. }
+            | ParseError::MalformedEntry { .. }
+            | ParseError::FileNameExtractionError { .. }
+            | ParseError::TypeConversionError { .. }
+            | ParseError::DuplicateColumns { .. }
+            | ParseError::UnknownEncoding { .. }
+            | ParseError::UnknownTimezone { .. }
+            | ParseError::UnknownDuplicatePolicy { .. }
+            | ParseError::InvalidFileKey { .. }
+            | ParseError::UnwritableValue { .. }
+            | ParseError::DuplicateFileKey { .. }
+            | ParseError::InvalidSchema { .. } => None,
+        }
+    }
+}
+
+/// Errors of queries on parsed data, e.g. row filters, aggregations and statistics.
+///
+/// Except for `ComputationError`, they are caused by the arguments of the query rather than
+/// by the data, so a server can report them as bad requests.
+#[derive(Debug)]
+pub enum QueryError {
+    /// Specifies that a row filter is not valid.
+    /// Includes the filter, the character offset of the problem and a description
+    InvalidFilter {
+        filter: String,
+        position: usize,
+        reason: String,
+    },
+
+    /// Specifies that an aggregation is not recognized.
+    /// Includes the name
+    UnknownAggregation { name: String },
+
+    /// Specifies that an argument of a query is not valid, e.g. a bucket length that is not a
+    /// duration. Includes the name of the argument, its value and the reason
+    InvalidArgument {
+        argument: String,
+        value: String,
+        reason: String,
+    },
+
+    /// Specifies that a column the query needs is not part of the data.
+    /// Includes the name of the column
+    UnknownColumn { column: String },
+
+    /// Specifies that the query could not be computed
+    ComputationError { source: PolarsError },
+}
+
+impl QueryError {
+    /// Returns true if the error is caused by the arguments of the query.
+    pub fn is_request_error(&self) -> bool {
+        !matches!(self, QueryError::ComputationError { .. })
+    }
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueryError::InvalidFilter {
+                filter,
+                position,
+                reason,
+            } => {
+                write!(
+                    f,
+                    "Invalid filter '{}' at character {}: {}",
+                    filter,
+                    position + 1,
+                    reason
+                )
             }
-            ParseError::ColumnMismatchError => {
-                write!(f, "Column mismatch")
+            QueryError::UnknownAggregation { name } => {
+                write!(f, "Unknown aggregation: {}", name)
             }
-            ParseError::DuplicateColumns => {
-                write!(f, "Duplicate columns")
+            QueryError::InvalidArgument {
+                argument,
+                value,
+                reason,
+            } => {
+                write!(f, "Invalid {} '{}': {}", argument, value, reason)
             }
-            ParseError::InvalidRegex => {
-                write!(f, "Invalid Regex")
+            QueryError::UnknownColumn { column } => {
+                write!(f, "Unknown column: {}", column)
             }
-            ParseError::EpochToDatetime(line) => {
-                write!(f, "Error converting epoch column to datetime: {}", line)
+            QueryError::ComputationError { .. } => {
+                write!(f, "Failed to compute the query")
             }
+        }?;
+
+        // Like `ParseError`, only the alternate format appends the wrapped error
+        if let (true, Some(source)) = (f.alternate(), self.source()) {
+            write!(f, ": {}", source)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for QueryError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            QueryError::ComputationError { source } => Some(source),
+            QueryError::InvalidFilter { .. }
+            | QueryError::UnknownAggregation { .. }
+            | QueryError::InvalidArgument { .. }
+            | QueryError::UnknownColumn { .. } => None,
         }
     }
 }
@@ -1,47 +1,35 @@
-use super::{parse_folder, ParseError};
-use crate::read_and_decode_lines;
-use lazy_static::lazy_static;
+use super::key::KsmFileKey;
+use super::schema::ColumnSchema;
+use super::{
+    parse_folder, DuplicatePolicy, FolderParseResult, IssueAction, Location, ParseError,
+    ParseIssue, ParseOptions, ParseReport, Timezone,
+};
+use crate::{check_field, decode_lines, format_column, is_gzip, read_and_decode_lines, write_line};
 use polars::prelude::*;
+use std::borrow::Cow;
 use std::collections::{HashMap, HashSet};
-use std::env;
-use std::io;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
 use std::path::Path;
 
-lazy_static! {
-    static ref COLUMN_DTYPE: HashMap<&'static str, DataType> = {
-        let mut m = HashMap::new();
-        m.insert("centervalue", DataType::Float64);
-        m.insert("check_wall_min_minlimit", DataType::Float64);
-        m.insert("check_wall_min_nomlimit", DataType::Float64);
-        m.insert("check_wall_mean_minlimit", DataType::Float64);
-        m.insert("check_wall_mean_nomlimit", DataType::Float64);
-        m.insert("diameter_outer_mean", DataType::Float64);
-        m.insert("wall_extra_percent", DataType::Float64);
-        m.insert("check_diameter_outer_mean_nomlimit", DataType::Float64);
-        m.insert("area_outer", DataType::Float64);
-        m.insert("diameter_outer_max", DataType::Float64);
-        m.insert("ovality", DataType::Float64);
-        m.insert("wall_min", DataType::Float64);
-        m.insert("area_wall", DataType::Float64);
-        m.insert("wall_mean", DataType::Float64);
-        m.insert("measure_time1970", DataType::Int64);
-        m
-    };
-}
-
 /// Adds specified columns as null columns to a mutable DataFrame.
 ///
 /// # Arguments
 /// * `dataframe` - A mutable reference to a DataFrame to which the null columns will be added.
 /// * `column_names` - A vector containing the names of the columns to be added.
+/// * `reference` - The DataFrame the columns are taken from, which determines their data types.
 ///
 /// # Returns
 /// A PolarsResult indicating success or failure.
-fn add_null_columns(dataframe: &mut DataFrame, column_names: &Vec<String>) -> PolarsResult<()> {
+fn add_null_columns(
+    dataframe: &mut DataFrame,
+    column_names: &Vec<String>,
+    reference: &DataFrame,
+) -> PolarsResult<()> {
     for column_name in column_names {
-        let dtype = match COLUMN_DTYPE.get(column_name.as_str()) {
-            Some(t) => t,
-            None => &DataType::String,
+        let dtype = match reference.column(column_name) {
+            Ok(column) => column.dtype(),
+            Err(_) => &DataType::String,
         };
         // Creating a full null column for each column name provided
         let null_column = Column::full_null(
@@ -55,18 +43,89 @@ fn add_null_columns(dataframe: &mut DataFrame, column_names: &Vec<String>) -> Po
     Ok(())
 }
 
+/// Returns the data type that values of both data types can be converted to.
+///
+/// Integers are promoted to Int64 and mixed numbers to Float64. Any other combination, such as a
+/// number and a string, falls back to String. Null columns take the other data type.
+fn common_type(a: &DataType, b: &DataType) -> DataType {
+    match (a, b) {
+        _ if a == b => a.clone(),
+        (DataType::Null, other) | (other, DataType::Null) => other.clone(),
+        _ if a.is_integer() && b.is_integer() => DataType::Int64,
+        _ if a.is_primitive_numeric() && b.is_primitive_numeric() => DataType::Float64,
+        _ => DataType::String,
+    }
+}
+
+/// Casts the columns that both DataFrames have with different data types to their common type,
+/// recording every converted column in `report`.
+///
+/// # Arguments
+/// * `a` - The first DataFrame.
+/// * `b` - The second DataFrame.
+/// * `report` - Receives an issue for every column that was converted.
+///
+/// # Returns
+/// A PolarsResult indicating success or failure.
+fn unify_column_types(
+    a: &mut DataFrame,
+    b: &mut DataFrame,
+    report: &mut ParseReport,
+) -> PolarsResult<()> {
+    let conflicts: Vec<(PlSmallStr, DataType)> = a
+        .get_columns()
+        .iter()
+        .filter_map(|column| {
+            let other = b.column(column.name()).ok()?;
+            (column.dtype() != other.dtype()).then(|| {
+                (
+                    column.name().clone(),
+                    common_type(column.dtype(), other.dtype()),
+                )
+            })
+        })
+        .collect();
+
+    for (name, dtype) in conflicts {
+        for dataframe in [&mut *a, &mut *b] {
+            let column = dataframe.column(&name)?;
+            if column.dtype() == &dtype {
+                continue;
+            }
+            if column.dtype() != &DataType::Null {
+                let reason = format!("Converted {} from {} to {}", name, column.dtype(), dtype);
+                report.issues.push(ParseIssue {
+                    location: Location::default(),
+                    action: IssueAction::Promoted,
+                    reason,
+                });
+            }
+            let converted = column.cast(&dtype)?;
+            dataframe.with_column(converted)?;
+        }
+    }
+    Ok(())
+}
+
 /// Ensures that both DataFrames have the same set of columns and inserts a row from one into another.
 ///
+/// Columns with different data types in the two DataFrames are converted to a common type first,
+/// and every conversion is recorded in `report`.
+///
 /// # Arguments
 /// * `main_dataframe` - The main DataFrame that will be modified.
 /// * `new_data_row` - The new DataFrame representing a single row to be inserted.
+/// * `report` - Receives an issue for every column that was converted.
 ///
 /// # Returns
 /// A PolarsResult containing the modified DataFrame or an error.
 fn align_dataframes_and_insert_row(
     main_dataframe: &mut DataFrame,
     new_data_row: &mut DataFrame,
+    report: &mut ParseReport,
 ) -> PolarsResult<DataFrame> {
+    unify_column_types(main_dataframe, new_data_row, report)?;
+
     // Determine columns that need to be added to each DataFrame to align them
     let (columns_to_add_to_dataframe, columns_to_add_to_new_row) = find_column_name_differences(
         &new_data_row.get_column_names(),
@@ -74,8 +133,8 @@ fn align_dataframes_and_insert_row(
     );
 
     // Add necessary columns as null columns to each DataFrame
-    add_null_columns(new_data_row, &columns_to_add_to_new_row)?;
-    add_null_columns(main_dataframe, &columns_to_add_to_dataframe)?;
+    add_null_columns(new_data_row, &columns_to_add_to_new_row, main_dataframe)?;
+    add_null_columns(main_dataframe, &columns_to_add_to_dataframe, new_data_row)?;
     // Align the new row with the main DataFrame and insert it
     align_and_insert_row(main_dataframe, new_data_row)
 }
@@ -113,76 +172,394 @@ fn align_and_insert_row(
 /// * `values` - A string containing corresponding values separated by tabs.
 ///
 /// # Returns
-/// * `PolarsResult<DataFrame>` - Returns a DataFrame constructed from the provided columns and values if successful.
-///   Returns a `PolarsError` if the DataFrame could not be successfully constructed (e.g., mismatch in the number of columns and values).
+/// * `Result<DataFrame, ParseError>` - Returns a DataFrame constructed from the provided columns and values if successful.
+///   Returns `ParseError::MalformedEntry` if the number of columns and values differ and
+///   `ParseError::DuplicateColumns` if a column name occurs more than once.
+///
+/// The columns are typed according to the built-in `ColumnSchema`.
 pub fn create_dataframe_from_columns_and_values(
     columns: &str,
     values: &str,
 ) -> Result<DataFrame, ParseError> {
-    let col_str_vec: Vec<&str> = columns.split("\t").collect();
-    let val_str_vec: Vec<&str> = values.split("\t").collect();
+    let schema = ColumnSchema::default();
+    let mut builder = EntryBuilder::new(&schema, DuplicatePolicy::Reject);
+    builder.push_entry(columns, values, 1, 2, &mut ParseReport::default())?;
+    builder.finish()
+}
+
+/// Outcome of appending a value to a column.
+enum Pushed {
+    Stored,
+    /// The column was converted to a wider type to hold the value, from the named type
+    Promoted(&'static str),
+    /// A non-empty value could not be converted and was stored as null
+    Coerced,
+}
 
-    if col_str_vec.len() != val_str_vec.len() {
-        return Err(ParseError::ColumnMismatchError);
+/// Values accumulated for a single column while reading entries.
+enum ColumnValues {
+    Float64(Vec<Option<f64>>),
+    Float32(Vec<Option<f32>>),
+    Int64(Vec<Option<i64>>),
+    Int32(Vec<Option<i32>>),
+    String(Vec<Option<String>>),
+}
+
+impl ColumnValues {
+    /// Creates an empty set of values for the given DataType. Types without a dedicated
+    /// variant are stored as strings.
+    fn new(data_type: &DataType) -> Self {
+        match data_type {
+            DataType::Float64 => ColumnValues::Float64(Vec::new()),
+            DataType::Float32 => ColumnValues::Float32(Vec::new()),
+            DataType::Int64 => ColumnValues::Int64(Vec::new()),
+            DataType::Int32 => ColumnValues::Int32(Vec::new()),
+            _ => ColumnValues::String(Vec::new()),
+        }
     }
 
-    let mut column_vec = Vec::new();
-    for (column, value) in col_str_vec.into_iter().zip(val_str_vec.into_iter()) {
-        let column = column.trim().trim_matches('"');
-        let data_type = match COLUMN_DTYPE.get(column) {
-            Some(t) => t,
-            None => &DataType::String,
-        };
+    fn len(&self) -> usize {
+        match self {
+            ColumnValues::Float64(v) => v.len(),
+            ColumnValues::Float32(v) => v.len(),
+            ColumnValues::Int64(v) => v.len(),
+            ColumnValues::Int32(v) => v.len(),
+            ColumnValues::String(v) => v.len(),
+        }
+    }
 
-        let column = parse_column(column, value, data_type);
-        column_vec.push(column);
+    /// Appends nulls until the column holds `height` values.
+    fn fill_nulls(&mut self, height: usize) {
+        match self {
+            ColumnValues::Float64(v) => v.resize(height, None),
+            ColumnValues::Float32(v) => v.resize(height, None),
+            ColumnValues::Int64(v) => v.resize(height, None),
+            ColumnValues::Int32(v) => v.resize(height, None),
+            ColumnValues::String(v) => v.resize(height, None),
+        }
     }
 
-    match DataFrame::new(column_vec) {
-        Ok(df) => Ok(df),
-        Err(e) => match e {
-            PolarsError::Duplicate(_) => Err(ParseError::DuplicateColumns),
-            _ => Err(ParseError::DataFrameCreationError),
-        },
+    /// Parses `value` and appends it.
+    ///
+    /// An integer column receiving a number it cannot hold, e.g. `0.25`, is converted in place
+    /// to the integer or float type that can, the way `common_type` unifies entries. Values
+    /// that are not numbers at all are stored as null.
+    fn push(&mut self, value: &str) -> Pushed {
+        fn parse_or_null<T: std::str::FromStr>(values: &mut Vec<Option<T>>, value: &str) -> Pushed {
+            let parsed = value.parse().ok();
+            let coerced = parsed.is_none() && !value.is_empty();
+            values.push(parsed);
+            if coerced {
+                Pushed::Coerced
+            } else {
+                Pushed::Stored
+            }
+        }
+
+        match self {
+            ColumnValues::Float64(v) => parse_or_null(v, value),
+            ColumnValues::Float32(v) => parse_or_null(v, value),
+            ColumnValues::Int64(v) => match value.parse() {
+                Ok(parsed) => {
+                    v.push(Some(parsed));
+                    Pushed::Stored
+                }
+                Err(_) => self.push_wider(value),
+            },
+            ColumnValues::Int32(v) => match value.parse() {
+                Ok(parsed) => {
+                    v.push(Some(parsed));
+                    Pushed::Stored
+                }
+                Err(_) => self.push_wider(value),
+            },
+            ColumnValues::String(v) => {
+                v.push(Some(value.to_owned()));
+                Pushed::Stored
+            }
+        }
+    }
+
+    /// Appends `value`, which this integer column cannot hold, after converting the column to a
+    /// wider type that can. Stores a null if no wider type can hold it.
+    fn push_wider(&mut self, value: &str) -> Pushed {
+        match self.promotion(value) {
+            Some(promoted) => {
+                let from = self.dtype_name();
+                *self = promoted;
+                self.push(value);
+                Pushed::Promoted(from)
+            }
+            None => {
+                self.fill_nulls(self.len() + 1);
+                if value.is_empty() {
+                    Pushed::Stored
+                } else {
+                    Pushed::Coerced
+                }
+            }
+        }
+    }
+
+    /// Returns the values converted to a type that can hold `value`, if a wider type than this
+    /// integer column can. Only finite numbers are converted to floats.
+    fn promotion(&mut self, value: &str) -> Option<ColumnValues> {
+        let fits_f64 = || value.parse::<f64>().is_ok_and(f64::is_finite);
+        match self {
+            ColumnValues::Int32(v) if value.parse::<i64>().is_ok() => Some(ColumnValues::Int64(
+                v.drain(..).map(|x| x.map(i64::from)).collect(),
+            )),
+            ColumnValues::Int32(v) if fits_f64() => Some(ColumnValues::Float64(
+                v.drain(..).map(|x| x.map(f64::from)).collect(),
+            )),
+            ColumnValues::Int64(v) if fits_f64() => Some(ColumnValues::Float64(
+                v.drain(..).map(|x| x.map(|x| x as f64)).collect(),
+            )),
+            _ => None,
+        }
+    }
+
+    /// Name of the DataType the values are stored as.
+    fn dtype_name(&self) -> &'static str {
+        match self {
+            ColumnValues::Float64(_) => "f64",
+            ColumnValues::Float32(_) => "f32",
+            ColumnValues::Int64(_) => "i64",
+            ColumnValues::Int32(_) => "i32",
+            ColumnValues::String(_) => "str",
+        }
+    }
+
+    fn into_column(self, name: PlSmallStr) -> Column {
+        match self {
+            ColumnValues::Float64(v) => Column::new(name, v),
+            ColumnValues::Float32(v) => Column::new(name, v),
+            ColumnValues::Int64(v) => Column::new(name, v),
+            ColumnValues::Int32(v) => Column::new(name, v),
+            ColumnValues::String(v) => Column::new(name, v),
+        }
     }
 }
 
-/// Parses a string value into a Column of specified data type.
-///
-/// This function reads a string value and tries to parse and convert it into a Column
-/// of a specific DataType. It covers parsing for common numerical types and dates.
-///
-/// # Arguments
-/// * `column` - A string slice that holds the name of the column to which the value belongs.
-/// * `value` - A string slice representing the value to be parsed into a Column.
-/// * `data_type` - The desired DataType of the Column after parsing the value.
+/// A value of a measurement entry with the name it is stored under.
+struct EntryField<'e> {
+    /// Name of the column in the DataFrame
+    name: Cow<'e, str>,
+    /// Name of the column in the entry, which determines the data type
+    column: &'e str,
+    value: &'e str,
+}
+
+/// Accumulates measurement entries column by column and materializes them as a DataFrame once.
 ///
-/// # Returns
-/// A `Result` that is either:
-/// - `Ok(Column)` - A new Column with the parsed value if successful.
-/// - `Err(PolarsError)` - An error if the parsing fails.
-fn parse_column(column: &str, value: &str, data_type: &DataType) -> Column {
-    match data_type {
-        DataType::Float64 => match value.parse::<f64>() {
-            Ok(parsed_value) => Column::new(PlSmallStr::from_str(column), [parsed_value]),
-            Err(_) => Column::full_null(PlSmallStr::from_str(column), 1, data_type),
-        },
-        // Similar parsing process for Float32.
-        DataType::Float32 => match value.parse::<f32>() {
-            Ok(parsed_value) => Column::new(PlSmallStr::from_str(column), [parsed_value]),
-            Err(_) => Column::full_null(PlSmallStr::from_str(column), 1, data_type),
-        },
-        // Similar parsing process for Int64.
-        DataType::Int64 => match value.parse::<i64>() {
-            Ok(parsed_value) => Column::new(PlSmallStr::from_str(column), [parsed_value]),
-            Err(_) => Column::full_null(PlSmallStr::from_str(column), 1, data_type),
-        },
-        // Similar parsing process for Int32.
-        DataType::Int32 => match value.parse::<i32>() {
-            Ok(parsed_value) => Column::new(PlSmallStr::from_str(column), [parsed_value]),
-            Err(_) => Column::full_null(PlSmallStr::from_str(column), 1, data_type),
-        },
-        _ => Column::new(PlSmallStr::from_str(column), [value]),
+/// Columns are kept in the order they first appear. A column that appears after the first
+/// entry is back-filled with nulls, and columns missing from an entry get a null for that row.
+struct EntryBuilder<'a> {
+    schema: &'a ColumnSchema,
+    duplicates: DuplicatePolicy,
+    names: Vec<PlSmallStr>,
+    columns: Vec<ColumnValues>,
+    index: HashMap<PlSmallStr, usize>,
+    height: usize,
+}
+
+impl<'a> EntryBuilder<'a> {
+    /// Creates an empty builder typing its columns according to `schema` and handling repeated
+    /// column names according to `duplicates`.
+    fn new(schema: &'a ColumnSchema, duplicates: DuplicatePolicy) -> Self {
+        EntryBuilder {
+            schema,
+            duplicates,
+            names: Vec::new(),
+            columns: Vec::new(),
+            index: HashMap::new(),
+            height: 0,
+        }
+    }
+
+    /// Adds one entry from a pair of tab separated column and value lines.
+    ///
+    /// The entry is validated before anything is stored, so a rejected entry leaves the
+    /// builder unchanged. Repeated column names are resolved by `resolve_duplicates` and
+    /// recorded in `report` at `column_line`. Values that cannot be converted to their
+    /// column's data type are stored as null and recorded in `report` at `values_line`.
+    ///
+    /// # Errors
+    /// * `ParseError::MalformedEntry` if the number of columns and values differ.
+    /// * `ParseError::DuplicateColumns` if a column name occurs more than once in the entry
+    ///   and duplicates are rejected.
+    ///
+    /// The errors do not carry a location; the caller knows which lines the entry came from.
+    fn push_entry(
+        &mut self,
+        columns: &str,
+        values: &str,
+        column_line: usize,
+        values_line: usize,
+        report: &mut ParseReport,
+    ) -> Result<(), ParseError> {
+        let names: Vec<&str> = columns
+            .split('\t')
+            .map(|column| column.trim().trim_matches('"'))
+            .collect();
+        let values: Vec<&str> = values.split('\t').collect();
+
+        if names.len() != values.len() {
+            return Err(ParseError::MalformedEntry {
+                location: Location::default(),
+                reason: format!("{} columns but {} values", names.len(), values.len()),
+            });
+        }
+        let entry = self.resolve_duplicates(&names, &values, column_line, report)?;
+
+        for EntryField {
+            name,
+            column,
+            value,
+        } in entry
+        {
+            let position = match self.index.get(name.as_ref()) {
+                Some(position) => *position,
+                None => self.add_column(&name, column),
+            };
+            let column = &mut self.columns[position];
+            match column.push(value) {
+                Pushed::Stored => {}
+                Pushed::Promoted(from) => {
+                    let reason = format!(
+                        "Converted {} from {} to {} to hold '{}'",
+                        name,
+                        from,
+                        column.dtype_name(),
+                        value
+                    );
+                    report.push(values_line, IssueAction::Promoted, reason);
+                }
+                Pushed::Coerced => {
+                    let reason = format!(
+                        "'{}' is not a valid {} for {}",
+                        value,
+                        column.dtype_name(),
+                        name
+                    );
+                    report.push(values_line, IssueAction::Coerced, reason);
+                }
+            }
+        }
+
+        // Pad the columns that were not part of this entry
+        self.height += 1;
+        for column in self.columns.iter_mut() {
+            if column.len() < self.height {
+                column.fill_nulls(self.height);
+            }
+        }
+        Ok(())
+    }
+
+    /// Pairs the column names of an entry with their values, resolving repeated names
+    /// according to `self.duplicates`. Renamed columns keep the data type of their original
+    /// name. Every duplicate that is kept, discarded or renamed is recorded in `report` at
+    /// `column_line`.
+    ///
+    /// # Errors
+    /// * `ParseError::DuplicateColumns` if a name occurs more than once and duplicates are
+    ///   rejected. Nothing is recorded in `report` in that case.
+    fn resolve_duplicates<'e>(
+        &self,
+        names: &[&'e str],
+        values: &[&'e str],
+        column_line: usize,
+        report: &mut ParseReport,
+    ) -> Result<Vec<EntryField<'e>>, ParseError> {
+        let field = |name: &'e str, value: &'e str| EntryField {
+            name: Cow::Borrowed(name),
+            column: name,
+            value,
+        };
+        let mut seen = HashSet::with_capacity(names.len());
+        if names.iter().all(|name| seen.insert(*name)) {
+            return Ok(names
+                .iter()
+                .zip(values)
+                .map(|(name, value)| field(name, value))
+                .collect());
+        }
+
+        let mut entry: Vec<EntryField> = Vec::with_capacity(names.len());
+        let mut positions: HashMap<&str, usize> = HashMap::with_capacity(names.len());
+        for (&name, &value) in names.iter().zip(values) {
+            let position = match positions.get(name) {
+                Some(position) => *position,
+                None => {
+                    positions.insert(name, entry.len());
+                    entry.push(field(name, value));
+                    continue;
+                }
+            };
+            match self.duplicates {
+                DuplicatePolicy::Reject => {
+                    return Err(ParseError::DuplicateColumns {
+                        location: Location::default(),
+                        column: name.to_string(),
+                    })
+                }
+                DuplicatePolicy::KeepFirst => {
+                    let reason = format!("Duplicate column {} with value '{}'", name, value);
+                    report.push(column_line, IssueAction::Discarded, reason);
+                }
+                DuplicatePolicy::KeepLast => {
+                    let previous = std::mem::replace(&mut entry[position].value, value);
+                    let reason = format!("Duplicate column {} with value '{}'", name, previous);
+                    report.push(column_line, IssueAction::Discarded, reason);
+                }
+                DuplicatePolicy::Rename => {
+                    // Skip suffixes that are taken by other columns of the entry
+                    let renamed = (2..)
+                        .map(|n| format!("{}_{}", name, n))
+                        .find(|renamed| {
+                            !names.contains(&renamed.as_str())
+                                && !entry.iter().any(|field| field.name == renamed.as_str())
+                        })
+                        .unwrap_or_default();
+                    let reason = format!("Duplicate column {} renamed to {}", name, renamed);
+                    report.push(column_line, IssueAction::Renamed, reason);
+                    entry.push(EntryField {
+                        name: Cow::Owned(renamed),
+                        column: name,
+                        value,
+                    });
+                }
+            }
+        }
+        Ok(entry)
+    }
+
+    /// Adds a column back-filled with nulls for all previous entries and returns its position.
+    /// The column is typed by the schema entry of `column`.
+    fn add_column(&mut self, name: &str, column: &str) -> usize {
+        let mut values = ColumnValues::new(&self.schema.data_type(column));
+        values.fill_nulls(self.height);
+
+        let name = PlSmallStr::from_str(name);
+        self.index.insert(name.clone(), self.columns.len());
+        self.names.push(name);
+        self.columns.push(values);
+        self.columns.len() - 1
+    }
+
+    /// Builds the DataFrame from the accumulated columns.
+    fn finish(self) -> Result<DataFrame, ParseError> {
+        let columns = self
+            .names
+            .into_iter()
+            .zip(self.columns)
+            .map(|(name, values)| values.into_column(name))
+            .collect();
+        DataFrame::new(columns).map_err(|source| ParseError::DataFrameCreationError {
+            location: Location::default(),
+            source,
+        })
     }
 }
 
@@ -212,92 +589,167 @@ fn find_column_name_differences(
 /// Reads measurement entries from an iterator over `io::Result<String>` representing lines from a file.
 ///
 /// This function processes pairs of lines from the given iterator, where the first line of each
-/// pair represents column names and the second line represents the corresponding values. These
-/// pairs are used to construct a DataFrame row by row. If any line read fails, or the pairs are
-/// incomplete, it returns an error.
+/// pair represents column names and the second line represents the corresponding values. The values
+/// are accumulated per column in an `EntryBuilder` and the DataFrame is created once all entries
+/// have been read. If any line read fails, or the pairs are incomplete, it returns an error.
+///
+/// With `options.lenient` set, malformed entries are skipped instead and recorded in the returned
+/// `ParseReport`. Column names that occur more than once in an entry are handled according to
+/// `options.duplicates` and always recorded, and values that cannot be converted to their
+/// column's data type are always stored as null and recorded.
+///
+/// Columns are typed by `options.schema`, and columns with a new name in the schema are renamed
+/// after the `local_time` column has been derived from `measure_time1970` and the
+/// `check_<name>_failed` columns have been decoded from `checkresult`.
 ///
 /// # Arguments
 /// * `lines_res` - An iterator over `io::Result<String>` which yields lines from a file.
+/// * `line_offset` - Number of lines preceding the first line of `lines_res`. Used to report
+///   line numbers relative to the start of the file when parsing appended entries.
+/// * `options` - Options controlling whether malformed entries are skipped and how columns are typed.
 ///
 /// # Returns
-/// * `Result<DataFrame, ParseError>` - On success, returns a DataFrame containing the data from all read entries.
+/// * `Result<(DataFrame, ParseReport), ParseError>` - On success, returns a DataFrame containing the data from all read entries
+///   and a report of the issues that were worked around.
 ///   Returns a `ParseError` on any form of reading or parsing failures, such as I/O errors, empty lines, or malformed entries.
 ///
 /// # Errors
+/// All errors carry the 1-based line number they relate to.
 /// * `ParseError::IOError` - if there's an I/O error reading a line.
-/// * `ParseError::MalformedEntry` - if there is a mismatch in the expected format, specifically if a value line is missing after a column line.
-/// * `ParseError::GeneralError` - for errors during DataFrame construction or data alignment.
+/// * `ParseError::MalformedEntry` - if there is a mismatch in the expected format, such as a value line missing after a column line
+///   or a different number of columns and values. Not returned in lenient mode.
+/// * `ParseError::DuplicateColumns` - if an entry repeats a column name and duplicates are rejected.
+///   Not returned in lenient mode.
+/// * `ParseError::DataFrameCreationError` - if the accumulated columns cannot be turned into a DataFrame.
+/// * `ParseError::EpochToDatetime` - if the local time column cannot be created.
+/// * `ParseError::ColumnCreationError` - if the `checkresult` column cannot be decoded.
 fn read_measurement_entries(
-    mut lines_res: impl Iterator<Item = io::Result<String>>,
-) -> Result<DataFrame, ParseError> {
-    //Create dataframe to hold return data
-    let mut dataframe = DataFrame::default();
+    lines_res: impl Iterator<Item = io::Result<String>>,
+    line_offset: usize,
+    options: &ParseOptions,
+) -> Result<(DataFrame, ParseReport), ParseError> {
+    //Create builder to accumulate the entries
+    let mut builder = EntryBuilder::new(&options.schema, options.duplicates);
+    let mut report = ParseReport::default();
+    //Pair every line with its 1-based line number
+    let mut lines_res = lines_res
+        .enumerate()
+        .map(|(index, line)| (line_offset + index + 1, line));
+    //Column row read in place of a missing value row
+    let mut next_column_row = None;
 
     loop {
         //Read columns row into string
-        let column_row = match lines_res.next() {
-            Some(Ok(line)) => line,
-            Some(Err(e)) => return Err(ParseError::IOError(e.to_string())),
-            None => break,
+        let (column_line, column_row) = match next_column_row.take() {
+            Some(row) => row,
+            None => match lines_res.next() {
+                Some((line_number, Ok(line))) => (line_number, line),
+                Some((line_number, Err(source))) => {
+                    return Err(ParseError::IOError {
+                        location: Location::line(line_number),
+                        source,
+                    })
+                }
+                None => break,
+            },
         };
 
-        //.dat file sometimes end with an empty line
-        if column_row.is_empty() {
+        //.dat file sometimes end with an empty line. In lenient mode skip it rather than
+        //stopping, so that entries appended after it are still read
+        if column_row.trim().is_empty() {
+            if options.lenient {
+                continue;
+            }
             break;
         }
 
         //Read values row into string
-        let values_row = match lines_res.next().transpose() {
-            Ok(Some(line)) => line,
-            Ok(None) => {
-                return Err(ParseError::MalformedEntry(String::from(
-                    "No value row after column row",
-                )))
+        let (values_line, values_row) = match lines_res.next() {
+            Some((line_number, Ok(line))) => (line_number, line),
+            Some((line_number, Err(source))) => {
+                return Err(ParseError::IOError {
+                    location: Location::line(line_number),
+                    source,
+                })
             }
-            Err(e) => return Err(ParseError::IOError(e.to_string())),
-        };
-
-        //Split the column and value stings and create a dataframe with a single row
-        let mut new_row = match create_dataframe_from_columns_and_values(&column_row, &values_row) {
-            Ok(df) => df,
-            Err(e) => {
-                match e {
-                    //Ignore entries with duplicate column names
-                    ParseError::DuplicateColumns => continue,
-                    _ => return Err(e),
+            None => {
+                let reason = String::from("No value row after column row");
+                if options.lenient {
+                    report.push(column_line, IssueAction::Skipped, reason);
+                    break;
                 }
+                return Err(ParseError::MalformedEntry {
+                    location: Location::line(column_line),
+                    reason,
+                });
             }
         };
 
-        match align_dataframes_and_insert_row(&mut dataframe, &mut new_row) {
-            Ok(df) => dataframe = df,
-            Err(_) => return Err(ParseError::DataAlignmentError),
+        //Column names are quoted and values are not, so in lenient mode a quoted name means
+        //the value row is missing. Continue with it as the column row of the next entry
+        if options.lenient && values_row.trim_start().starts_with('"') {
+            let reason = String::from("No value row after column row");
+            report.push(column_line, IssueAction::Skipped, reason);
+            next_column_row = Some((values_line, values_row));
+            continue;
+        }
+
+        //Split the column and value stings and add them to the builder
+        if let Err(e) = builder.push_entry(
+            &column_row,
+            &values_row,
+            column_line,
+            values_line,
+            &mut report,
+        ) {
+            match e {
+                //Skip entries with rejected duplicate column names
+                ParseError::DuplicateColumns { column, .. } if options.lenient => {
+                    let reason = format!("Duplicate column {}", column);
+                    report.push(column_line, IssueAction::Skipped, reason);
+                }
+                //The values do not fit the columns
+                ParseError::MalformedEntry { reason, .. } if options.lenient => {
+                    report.push(values_line, IssueAction::Skipped, reason);
+                }
+                ParseError::MalformedEntry { .. } => return Err(e.at_line(values_line)),
+                _ => return Err(e.at_line(column_line)),
+            }
         }
     }
-    let mut dataframe = add_local_datetime_column(dataframe)?;
+    let dataframe = builder.finish()?;
+    //Every entry was skipped, so there are no columns to derive from
+    if dataframe.width() == 0 {
+        return Ok((dataframe, report));
+    }
+    let mut dataframe = add_local_datetime_column(dataframe, &options.timezone)?;
+    options.schema.checks().add_columns(&mut dataframe)?;
+    options
+        .schema
+        .rename_columns(&mut dataframe, &mut report)
+        .map_err(|source| ParseError::DataFrameCreationError {
+            location: Location::default(),
+            source,
+        })?;
     dataframe.shrink_to_fit(); // Not shrinking causes extreme bloating
-    Ok(dataframe)
+    Ok((dataframe, report))
 }
 
 /// Converts the epoch time from the 'measure_time1970' column of a DataFrame
-/// into a local DateTime based on the timezone specified in the environment variable 'TIMEZONE'.
+/// into a local DateTime in the given timezone.
 /// Adds the resulting DateTime as a new column 'local_time' to the DataFrame.
 ///
 /// # Parameters
 /// - `dataframe`: A DataFrame containing a 'measure_time1970' column with epoch times.
+/// - `timezone`: The timezone of the 'local_time' column, taken from the parse options.
 ///
 /// # Returns
 /// - `Result<DataFrame, ParseError>`: The modified DataFrame with the new 'local_time' column,
 ///   or a `ParseError` if there is an error during the conversion.
-///
-/// # Behavior
-/// - The function reads the timezone from the 'TIMEZONE' environment variable,
-///   defaults to "Europe/Stockholm" if not set, and converts the epoch time into a
-///   local DateTime in the specified timezone.
-fn add_local_datetime_column(mut dataframe: DataFrame) -> Result<DataFrame, ParseError> {
-    //Read timezone from environment variable
-    let timezone = env::var("TIMEZONE").unwrap_or(String::from("Europe/Stockholm"));
-
+fn add_local_datetime_column(
+    mut dataframe: DataFrame,
+    timezone: &Timezone,
+) -> Result<DataFrame, ParseError> {
     //Create local_time column
     dataframe = match dataframe
         .lazy()
@@ -307,12 +759,17 @@ fn add_local_datetime_column(mut dataframe: DataFrame) -> Result<DataFrame, Pars
                 Some("UTC".into()),
             ))
             .dt()
-            .convert_time_zone(PlSmallStr::from_string(timezone))
+            .convert_time_zone(PlSmallStr::from_str(timezone.name()))
             .alias("local_time")])
         .collect()
     {
         Ok(df) => df,
-        Err(e) => return Err(ParseError::EpochToDatetime(e.to_string())),
+        Err(source) => {
+            return Err(ParseError::EpochToDatetime {
+                location: Location::default(),
+                source,
+            })
+        }
     };
 
     Ok(dataframe)
@@ -321,29 +778,430 @@ fn add_local_datetime_column(mut dataframe: DataFrame) -> Result<DataFrame, Pars
 /// Parses a .dat file at the specified path to construct a DataFrame.
 ///
 /// This function leverages the `read_measurement_entries` to parse the file. It expects the file
-/// to be encoded in a manner compatible with the encoding settings of `read_and_decode_lines`. Each
+/// to be encoded as specified by the encoding in `options`. Each
 /// entry in the file should consist of consecutive lines; the first containing column names and the
-/// second containing corresponding values.
+/// second containing corresponding values. Files with a `.gz` extension are decompressed first.
 ///
 /// # Type Parameters
 /// * `P` - The type of the path which must implement `AsRef<Path>`, `Display`, and be copyable.
 ///
 /// # Arguments
 /// * `file_path` - A reference to the path of the .dat file.
+/// * `options` - Options controlling how the file is decoded and whether malformed entries are skipped.
 ///
 /// # Returns
 /// * `Result<DataFrame, ParseError>` where `DataFrame` contains the combined data from the file.
 /// * `ParseError::InvalidFile` if the file cannot be opened or read.
+/// * Errors inherited from `read_measurement_entries` function on parsing or DataFrame construction issues,
+///   with the file path added to their location.
+pub fn parse_dat_file<P: AsRef<Path>>(
+    file_path: P,
+    options: &ParseOptions,
+) -> Result<DataFrame, ParseError> {
+    parse_dat_file_with_report(file_path, options).map(|(dataframe, _)| dataframe)
+}
+
+/// Parses a .dat file like `parse_dat_file` and also returns a report of every entry that was
+/// skipped or had values coerced to null, with the file path and line number of each.
+pub fn parse_dat_file_with_report<P: AsRef<Path>>(
+    file_path: P,
+    options: &ParseOptions,
+) -> Result<(DataFrame, ParseReport), ParseError> {
+    match read_and_decode_lines(&file_path, options) {
+        Ok(lines) => match read_measurement_entries(lines, 0, options) {
+            Ok((dataframe, report)) => Ok((dataframe, report.with_path(&file_path))),
+            Err(e) => Err(e.with_path(&file_path)),
+        },
+        Err(source) => Err(ParseError::InvalidFile {
+            location: Location::file(&file_path),
+            source,
+        }),
+    }
+}
+
+/// Parses measurement entries from any source implementing `Read`.
+///
+/// The content is decoded the same way as in `parse_dat_file`, which makes this function suitable
+/// for data that does not live in a file, such as uploads, archive entries or test fixtures.
+///
+/// # Arguments
+/// * `reader` - The source of the .dat content.
+/// * `options` - Options controlling how the content is decoded and whether malformed entries are skipped.
+///
+/// # Returns
+/// * `Result<DataFrame, ParseError>` where `DataFrame` contains the combined data from the reader.
+/// * `ParseError::IOError` if the content could not be read before decoding.
 /// * Errors inherited from `read_measurement_entries` function on parsing or DataFrame construction issues.
-pub fn parse_dat_file<P: AsRef<Path>>(file_path: P) -> Result<DataFrame, ParseError> {
-    match read_and_decode_lines(&file_path) {
-        Ok(lines) => read_measurement_entries(lines),
-        Err(_) => Err(ParseError::InvalidFile(
-            file_path.as_ref().to_string_lossy().into_owned(),
-        )),
+pub fn parse_dat_reader<R: Read>(
+    reader: R,
+    options: &ParseOptions,
+) -> Result<DataFrame, ParseError> {
+    parse_dat_reader_with_report(reader, options).map(|(dataframe, _)| dataframe)
+}
+
+/// Parses measurement entries like `parse_dat_reader` and also returns a report of every entry
+/// that was skipped or had values coerced to null.
+pub fn parse_dat_reader_with_report<R: Read>(
+    reader: R,
+    options: &ParseOptions,
+) -> Result<(DataFrame, ParseReport), ParseError> {
+    match decode_lines(reader, options) {
+        Ok(lines) => read_measurement_entries(lines, 0, options),
+        Err(source) => Err(ParseError::IOError {
+            location: Location::default(),
+            source,
+        }),
+    }
+}
+
+/// Parses measurement entries from an in-memory buffer.
+///
+/// Convenience wrapper around `parse_dat_reader`.
+pub fn parse_dat_bytes(bytes: &[u8], options: &ParseOptions) -> Result<DataFrame, ParseError> {
+    parse_dat_reader(bytes, options)
+}
+
+/// Parses every .dat file in a folder, stopping at the first file that cannot be parsed.
+///
+/// Use `parse_dat_folder_parallel` to keep the files that could be parsed when some fail.
+pub fn parse_dat_folder<P: AsRef<Path>>(
+    dir: P,
+    options: &ParseOptions,
+) -> Result<HashMap<KsmFileKey, DataFrame>, ParseError> {
+    parse_folder(dir, parse_dat_file, "dat", options)?.into_result()
+}
+
+/// Parses every .dat file in a folder in parallel, with a result for every file.
+///
+/// # Errors
+/// Only errors listing the folder are returned. Files that cannot be parsed are collected in
+/// `FolderParseResult::failed`.
+pub fn parse_dat_folder_parallel<P: AsRef<Path>>(
+    dir: P,
+    options: &ParseOptions,
+) -> Result<FolderParseResult, ParseError> {
+    parse_folder(dir, parse_dat_file, "dat", options)
+}
+
+/// Writes measurement entries in the .dat format, encoded in ISO-8859-10.
+///
+/// Every row is written as a pair of tab separated lines: the quoted names of the columns that
+/// have a value in the row, followed by the values. Nulls are left out of the entry, which is
+/// how the parser represents columns missing from an entry. The `local_time` column is not
+/// written since the parser derives it from `measure_time1970`.
+///
+/// A DataFrame returned by `parse_dat_file` is read back with the same values by
+/// `parse_dat_reader`. Columns can come back in a different order if a column is null in the
+/// first rows, and columns that only contain nulls are not read back at all.
+///
+/// # Arguments
+/// * `writer` - The destination of the .dat content.
+/// * `dataframe` - The measurement entries to write.
+///
+/// # Errors
+/// All errors carry the 1-based line of the output they relate to.
+/// * `ParseError::MalformedEntry` if a row has no values at all.
+/// * `ParseError::UnwritableValue` if a column name or value cannot be represented in the format,
+///   e.g. a tab, a line break or a character outside ISO-8859-10.
+/// * `ParseError::ColumnCreationError` if a column cannot be converted to strings.
+/// * `ParseError::WriteError` if writing to `writer` fails.
+pub fn write_dat<W: Write>(mut writer: W, dataframe: &DataFrame) -> Result<(), ParseError> {
+    let columns = dataframe
+        .get_columns()
+        .iter()
+        .filter(|column| column.name() != "local_time")
+        .map(|column| {
+            let name = column.name().as_str();
+            check_field(name, name, 1, &["\t", "\""], true)?;
+            let values =
+                format_column(column).map_err(|source| ParseError::ColumnCreationError {
+                    location: Location::default(),
+                    column: name.to_string(),
+                    source,
+                })?;
+            Ok((name, values))
+        })
+        .collect::<Result<Vec<_>, ParseError>>()?;
+
+    for row in 0..dataframe.height() {
+        let column_line = 2 * row + 1;
+        let values_line = column_line + 1;
+
+        let mut names = Vec::with_capacity(columns.len());
+        let mut values = Vec::with_capacity(columns.len());
+        for (name, column_values) in &columns {
+            if let Some(value) = &column_values[row] {
+                check_field(value, name, values_line, &["\t"], false)?;
+                names.push(format!("\"{}\"", name));
+                values.push(value.as_str());
+            }
+        }
+        if names.is_empty() {
+            return Err(ParseError::MalformedEntry {
+                location: Location::line(column_line),
+                reason: format!("Row {} has no values", row),
+            });
+        }
+
+        write_line(&mut writer, &names.join("\t"), column_line)?;
+        write_line(&mut writer, &values.join("\t"), values_line)?;
+    }
+    writer.flush().map_err(|source| ParseError::WriteError {
+        location: Location::default(),
+        source,
+    })
+}
+
+/// Number of bytes preceding a cursor's offset that are remembered to detect rewritten files.
+const FINGERPRINT_LEN: usize = 256;
+
+/// Position in a .dat file up to which all complete entries have been parsed.
+///
+/// Besides the byte offset, the cursor remembers the bytes immediately before it. A file that
+/// has been truncated or rewritten in place will no longer contain those bytes at the same
+/// position, which forces a full re-parse instead of appending to stale data.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DatCursor {
+    offset: u64,
+    line: usize,
+    fingerprint: Vec<u8>,
+    /// Length of the file when the cursor was created
+    file_len: u64,
+    /// Number of returned entries parsed from the unterminated end of the file
+    pending_entries: usize,
+}
+
+impl DatCursor {
+    /// Byte offset directly after the last complete entry that has been parsed.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Returns true if the file ended with an incomplete entry that was left for the next call.
+    pub fn has_pending(&self) -> bool {
+        self.file_len > self.offset
     }
+
+    /// Number of entries at the end of the returned DataFrame that were parsed from the
+    /// unterminated end of a file that stopped growing. They are parsed again by the next call,
+    /// so they have to be removed before appending the entries it returns.
+    pub fn pending_entries(&self) -> usize {
+        self.pending_entries
+    }
+}
+
+/// Outcome of an incremental parse with `parse_dat_file_from`.
+#[derive(Debug)]
+pub enum DatUpdate {
+    /// The file was parsed from the beginning. The DataFrame replaces any previously parsed data.
+    Full(DataFrame),
+    /// Only entries appended after the cursor were parsed. The DataFrame is empty if nothing new
+    /// was found and should otherwise be appended with `append_entries`.
+    Appended(DataFrame),
 }
 
-pub fn parse_dat_folder<P: AsRef<Path>>(dir: P) -> Result<HashMap<String, DataFrame>, ParseError> {
-    parse_folder(dir, parse_dat_file, "dat")
+/// Parses the entries of a .dat file that were appended after the given cursor.
+///
+/// Only complete column/value line pairs are parsed. A trailing entry that is still being
+/// written is left for the next call, and the returned cursor points to the start of it.
+/// If the file has not grown since the previous call, the end of the file ends the trailing
+/// entry instead: a final value line without a newline is parsed, and a column line without
+/// a value line is reported like any other malformed entry. As the writer may still finish
+/// the final line, the cursor stays at the start of the trailing entry, and the entries
+/// parsed from it are counted in `DatCursor::pending_entries` instead.
+/// If the file is shorter than the cursor or the bytes before the cursor have changed, the
+/// file is considered rewritten and is parsed from the beginning. A default cursor always
+/// results in a full parse. Gzip compressed files are always parsed in full.
+///
+/// # Arguments
+/// * `file_path` - Path of the .dat file.
+/// * `cursor` - Cursor returned by the previous call for the same file.
+/// * `options` - Options controlling how the file is decoded and whether malformed entries are skipped.
+///
+/// # Returns
+/// * `Result<(DatUpdate, DatCursor, ParseReport), ParseError>` - The parsed entries, the cursor to pass on the
+///   next call and a report of the issues found in the parsed entries.
+///
+/// # Errors
+/// * `ParseError::InvalidFile` if the file cannot be opened.
+/// * `ParseError::ReadMetadataError` if the file size cannot be read.
+/// * `ParseError::IOError` if reading the file fails.
+/// * Errors inherited from `read_measurement_entries`, with line numbers counted from the start of the file.
+pub fn parse_dat_file_from<P: AsRef<Path>>(
+    file_path: P,
+    cursor: &DatCursor,
+    options: &ParseOptions,
+) -> Result<(DatUpdate, DatCursor, ParseReport), ParseError> {
+    if is_gzip(&file_path) {
+        let (dataframe, report) = parse_dat_file_with_report(file_path, options)?;
+        return Ok((DatUpdate::Full(dataframe), DatCursor::default(), report));
+    }
+
+    let io_error = |source| ParseError::IOError {
+        location: Location::file(&file_path),
+        source,
+    };
+    let mut file = File::open(&file_path).map_err(|source| ParseError::InvalidFile {
+        location: Location::file(&file_path),
+        source,
+    })?;
+    let file_len = file
+        .metadata()
+        .map_err(|source| ParseError::ReadMetadataError {
+            location: Location::file(&file_path),
+            source,
+        })?
+        .len();
+
+    //Resume only if the file still contains the data the cursor was created from
+    let resume = cursor.offset > 0
+        && file_len >= cursor.offset
+        && matches_fingerprint(&mut file, cursor).map_err(io_error)?;
+    let (start, start_line) = if resume {
+        (cursor.offset, cursor.line)
+    } else {
+        (0, 0)
+    };
+
+    //Read everything after the starting point
+    let mut bytes = Vec::new();
+    file.seek(SeekFrom::Start(start))
+        .and_then(|_| file.read_to_end(&mut bytes))
+        .map_err(io_error)?;
+    let (chunk_len, chunk_lines) = complete_entries_len(&bytes);
+    let (chunk, tail) = bytes.split_at(chunk_len);
+    //A trailing entry is no longer being written once the file has stopped growing, but it is
+    //only parsed for this call, as appended bytes would continue its final line
+    let stopped_growing = cursor.file_len > 0 && file_len == cursor.file_len;
+    let tail = if stopped_growing { tail } else { &[] };
+
+    //Remember the bytes preceding the new offset
+    let mut fingerprint = if resume {
+        cursor.fingerprint.clone()
+    } else {
+        Vec::new()
+    };
+    fingerprint.extend_from_slice(&chunk[chunk.len().saturating_sub(FINGERPRINT_LEN)..]);
+    let fingerprint = fingerprint.split_off(fingerprint.len().saturating_sub(FINGERPRINT_LEN));
+    let mut new_cursor = DatCursor {
+        offset: start + chunk.len() as u64,
+        line: start_line + chunk_lines,
+        fingerprint,
+        file_len,
+        pending_entries: 0,
+    };
+
+    //Nothing new since the last call
+    if resume && chunk.is_empty() && tail.is_empty() {
+        return Ok((
+            DatUpdate::Appended(DataFrame::default()),
+            new_cursor,
+            ParseReport::default(),
+        ));
+    }
+
+    let parse = |bytes: &[u8], first_line: usize| {
+        decode_lines(bytes, options)
+            .map_err(io_error)
+            .and_then(|lines| read_measurement_entries(lines, first_line, options))
+            .map_err(|e| e.with_path(&file_path))
+    };
+    let (mut dataframe, mut report) = parse(chunk, start_line)?;
+    if !tail.is_empty() {
+        let (pending, pending_report) = parse(tail, start_line + chunk_lines)?;
+        new_cursor.pending_entries = pending.height();
+        dataframe = append_entries(&dataframe, pending).map_err(|e| e.with_path(&file_path))?;
+        report.issues.extend(pending_report.issues);
+    }
+    let report = report.with_path(&file_path);
+    if resume {
+        Ok((DatUpdate::Appended(dataframe), new_cursor, report))
+    } else {
+        Ok((DatUpdate::Full(dataframe), new_cursor, report))
+    }
+}
+
+/// Checks that the bytes preceding the cursor's offset in `file` are the ones stored in the cursor.
+fn matches_fingerprint(file: &mut File, cursor: &DatCursor) -> io::Result<bool> {
+    let mut stored = vec![0; cursor.fingerprint.len()];
+    file.seek(SeekFrom::Start(cursor.offset - stored.len() as u64))?;
+    file.read_exact(&mut stored)?;
+    Ok(stored == cursor.fingerprint)
+}
+
+/// Returns the number of leading bytes in `bytes` that make up complete entries, together with
+/// the number of lines they span.
+///
+/// An entry is complete when both its column line and its value line are terminated by a
+/// newline. Empty lines between entries are counted as consumed.
+fn complete_entries_len(bytes: &[u8]) -> (usize, usize) {
+    let (mut consumed, mut consumed_lines) = (0, 0);
+    let (mut position, mut lines) = (0, 0);
+    let mut awaiting_values = false;
+
+    for line in bytes.split_inclusive(|&b| b == b'\n') {
+        //The last line is still being written
+        if !line.ends_with(b"\n") {
+            break;
+        }
+        position += line.len();
+        lines += 1;
+
+        if awaiting_values || line.trim_ascii().is_empty() {
+            awaiting_values = false;
+            (consumed, consumed_lines) = (position, lines);
+        } else {
+            awaiting_values = true;
+        }
+    }
+    (consumed, consumed_lines)
+}
+
+/// Appends newly parsed entries to a previously parsed DataFrame.
+///
+/// Columns missing from either DataFrame are added as null columns before the rows are stacked.
+/// Columns with different data types, e.g. from files written by different gauge firmware, are
+/// converted to a common type: integers to Int64, mixed numbers to Float64 and anything else
+/// to String.
+///
+/// # Arguments
+/// * `dataframe` - The previously parsed entries.
+/// * `new_entries` - Entries returned in `DatUpdate::Appended`.
+///
+/// # Returns
+/// * `Result<DataFrame, ParseError>` - The combined DataFrame.
+///
+/// # Errors
+/// * `ParseError::DataAlignmentError` if the DataFrames cannot be aligned or stacked.
+pub fn append_entries(
+    dataframe: &DataFrame,
+    new_entries: DataFrame,
+) -> Result<DataFrame, ParseError> {
+    append_entries_with_report(dataframe, new_entries).map(|(dataframe, _)| dataframe)
+}
+
+/// Appends entries like `append_entries` and also returns a report of every column that was
+/// converted to a common data type.
+pub fn append_entries_with_report(
+    dataframe: &DataFrame,
+    mut new_entries: DataFrame,
+) -> Result<(DataFrame, ParseReport), ParseError> {
+    let mut report = ParseReport::default();
+    if dataframe.width() == 0 {
+        return Ok((new_entries, report));
+    }
+    if new_entries.height() == 0 {
+        return Ok((dataframe.clone(), report));
+    }
+
+    let mut dataframe = dataframe.clone();
+    let mut combined =
+        align_dataframes_and_insert_row(&mut dataframe, &mut new_entries, &mut report).map_err(
+            |source| ParseError::DataAlignmentError {
+                location: Location::default(),
+                source,
+            },
+        )?;
+    combined.as_single_chunk_par();
+    Ok((combined, report))
 }
@@ -0,0 +1,296 @@
+/// Module describing how the columns of `.dat` files are typed and named.
+///
+/// A `ColumnSchema` maps column names to their data type, unit and an optional new name. The
+/// built-in schema covers the known gauge channels, and additional columns can be loaded from a
+/// TOML or JSON file so that new channels do not require a recompile. The schema also holds the
+/// names of the bits of the `checkresult` column.
+use super::checkresult::{CheckFlag, CheckResultMapping};
+use super::{IssueAction, Location, ParseError, ParseIssue, ParseReport};
+use polars::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+/// Columns the parser and the endpoints rely on. They cannot be renamed, and no column can be
+/// renamed to them.
+const RESERVED_COLUMNS: [&str; 2] = ["measure_time1970", "local_time"];
+
+/// Data type of a measurement column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColumnType {
+    F64,
+    F32,
+    I64,
+    I32,
+    Str,
+}
+
+impl ColumnType {
+    /// Returns the polars DataType the column is stored as.
+    pub fn data_type(&self) -> DataType {
+        match self {
+            ColumnType::F64 => DataType::Float64,
+            ColumnType::F32 => DataType::Float32,
+            ColumnType::I64 => DataType::Int64,
+            ColumnType::I32 => DataType::Int32,
+            ColumnType::Str => DataType::String,
+        }
+    }
+}
+
+/// Describes a single measurement column.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ColumnSpec {
+    /// Name of the column in the .dat files
+    pub name: String,
+    pub dtype: ColumnType,
+    /// Unit of the values, e.g. "mm"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unit: Option<String>,
+    /// Name of the column in the parsed DataFrame, if it differs from `name`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rename: Option<String>,
+}
+
+impl ColumnSpec {
+    fn new(name: &str, dtype: ColumnType) -> Self {
+        ColumnSpec {
+            name: name.to_string(),
+            dtype,
+            unit: None,
+            rename: None,
+        }
+    }
+}
+
+/// Layout of a schema file. In TOML every column is a `[[columns]]` table, in JSON the
+/// columns are an array under the `columns` key. The `checks` name the `checkresult` bits in
+/// the same layout, e.g. `[[checks]]` tables with a `name` and a `bit`.
+#[derive(Serialize, Deserialize)]
+struct SchemaFile {
+    #[serde(default)]
+    columns: Vec<ColumnSpec>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    checks: Option<Vec<CheckFlag>>,
+}
+
+/// Column types, units and names used when parsing measurement entries.
+///
+/// Columns that are not part of the schema are parsed as strings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnSchema {
+    /// Columns by their name in the .dat files, ordered so that renames are applied in a fixed order
+    columns: BTreeMap<String, ColumnSpec>,
+    checks: CheckResultMapping,
+}
+
+impl Default for ColumnSchema {
+    /// The built-in schema of the known gauge channels.
+    fn default() -> Self {
+        ColumnSchema::new([
+            ColumnSpec::new("centervalue", ColumnType::F64),
+            ColumnSpec::new("check_wall_min_minlimit", ColumnType::F64),
+            ColumnSpec::new("check_wall_min_nomlimit", ColumnType::F64),
+            ColumnSpec::new("check_wall_mean_minlimit", ColumnType::F64),
+            ColumnSpec::new("check_wall_mean_nomlimit", ColumnType::F64),
+            ColumnSpec::new("diameter_outer_mean", ColumnType::F64),
+            ColumnSpec::new("wall_extra_percent", ColumnType::F64),
+            ColumnSpec::new("check_diameter_outer_mean_nomlimit", ColumnType::F64),
+            ColumnSpec::new("area_outer", ColumnType::F64),
+            ColumnSpec::new("diameter_outer_max", ColumnType::F64),
+            ColumnSpec::new("ovality", ColumnType::F64),
+            ColumnSpec::new("wall_min", ColumnType::F64),
+            ColumnSpec::new("area_wall", ColumnType::F64),
+            ColumnSpec::new("wall_mean", ColumnType::F64),
+            ColumnSpec::new("measure_time1970", ColumnType::I64),
+        ])
+    }
+}
+
+impl ColumnSchema {
+    /// Creates a schema of the given columns without named `checkresult` bits. A later column
+    /// replaces an earlier one with the same name.
+    pub fn new(columns: impl IntoIterator<Item = ColumnSpec>) -> Self {
+        let mut schema = ColumnSchema {
+            columns: BTreeMap::new(),
+            checks: CheckResultMapping::default(),
+        };
+        schema.extend(columns);
+        schema
+    }
+
+    /// Adds columns to the schema, replacing the columns with the same name.
+    pub fn extend(&mut self, columns: impl IntoIterator<Item = ColumnSpec>) {
+        for column in columns {
+            self.columns.insert(column.name.clone(), column);
+        }
+    }
+
+    /// Sets the names of the bits of the `checkresult` column.
+    pub fn with_checks(mut self, checks: CheckResultMapping) -> Self {
+        self.checks = checks;
+        self
+    }
+
+    /// Parses the columns of a TOML schema and adds them to the built-in schema.
+    ///
+    /// # Errors
+    /// * `ParseError::InvalidSchema` if the content is not a valid schema or its renames are
+    ///   invalid, see `validate_renames`.
+    pub fn from_toml(content: &str) -> Result<Self, ParseError> {
+        let file: SchemaFile = toml::from_str(content).map_err(|e| ParseError::InvalidSchema {
+            location: Location::default(),
+            reason: e.to_string(),
+        })?;
+        ColumnSchema::with_defaults(file)
+    }
+
+    /// Parses the columns of a JSON schema and adds them to the built-in schema.
+    ///
+    /// # Errors
+    /// * `ParseError::InvalidSchema` if the content is not a valid schema or its renames are
+    ///   invalid, see `validate_renames`.
+    pub fn from_json(content: &str) -> Result<Self, ParseError> {
+        let file: SchemaFile =
+            serde_json::from_str(content).map_err(|e| ParseError::InvalidSchema {
+                location: Location::default(),
+                reason: e.to_string(),
+            })?;
+        ColumnSchema::with_defaults(file)
+    }
+
+    /// Reads a schema file, choosing the format from its `.toml` or `.json` extension.
+    ///
+    /// # Errors
+    /// * `ParseError::InvalidFile` if the file cannot be read.
+    /// * `ParseError::InvalidSchema` if the extension is unknown or the content is not a valid schema.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, ParseError> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path).map_err(|source| ParseError::InvalidFile {
+            location: Location::file(path),
+            source,
+        })?;
+        let schema = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("toml") => ColumnSchema::from_toml(&content),
+            Some("json") => ColumnSchema::from_json(&content),
+            _ => Err(ParseError::InvalidSchema {
+                location: Location::default(),
+                reason: String::from("Expected a .toml or .json file"),
+            }),
+        };
+        schema.map_err(|e| e.with_path(path))
+    }
+
+    fn with_defaults(file: SchemaFile) -> Result<Self, ParseError> {
+        let mut schema = ColumnSchema::default();
+        schema.extend(file.columns);
+        if let Some(checks) = file.checks {
+            schema.checks = CheckResultMapping::new(checks)?;
+        }
+        schema.validate_renames()?;
+        Ok(schema)
+    }
+
+    /// Checks that the new names of the columns can be applied to every parsed DataFrame.
+    ///
+    /// # Errors
+    /// * `ParseError::InvalidSchema` if `measure_time1970` is renamed, a column is renamed to
+    ///   `measure_time1970`, `local_time` or the column of a check, or a new name is already the
+    ///   name or new name of another column.
+    pub fn validate_renames(&self) -> Result<(), ParseError> {
+        let invalid = |reason: String| ParseError::InvalidSchema {
+            location: Location::default(),
+            reason,
+        };
+        let mut new_names = HashSet::new();
+        for column in self.columns.values() {
+            let Some(rename) = column.rename.as_deref() else {
+                continue;
+            };
+            if RESERVED_COLUMNS.contains(&column.name.as_str()) {
+                return Err(invalid(format!("Column {} cannot be renamed", column.name)));
+            }
+            let is_check_column = self
+                .checks
+                .checks()
+                .iter()
+                .any(|check| CheckResultMapping::column_name(&check.name) == rename);
+            if RESERVED_COLUMNS.contains(&rename) || is_check_column {
+                return Err(invalid(format!(
+                    "Column {} cannot be renamed to {}, which is reserved",
+                    column.name, rename
+                )));
+            }
+            let is_other_column = rename != column.name && self.columns.contains_key(rename);
+            if is_other_column || !new_names.insert(rename) {
+                return Err(invalid(format!(
+                    "Column {} cannot be renamed to {}, which is used by another column",
+                    column.name, rename
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the description of a column by its name in the .dat files.
+    pub fn get(&self, name: &str) -> Option<&ColumnSpec> {
+        self.columns.get(name)
+    }
+
+    /// Returns the DataType of a column by its name in the .dat files. Unknown columns are strings.
+    pub fn data_type(&self, name: &str) -> DataType {
+        match self.columns.get(name) {
+            Some(column) => column.dtype.data_type(),
+            None => DataType::String,
+        }
+    }
+
+    /// Returns the unit of a column by its name in the parsed DataFrame.
+    pub fn unit(&self, name: &str) -> Option<&str> {
+        self.columns
+            .values()
+            .find(|column| column.rename.as_deref().unwrap_or(&column.name) == name)
+            .and_then(|column| column.unit.as_deref())
+    }
+
+    /// Returns the names of the bits of the `checkresult` column, which are empty unless they
+    /// are given in a schema file or with `with_checks`.
+    pub fn checks(&self) -> &CheckResultMapping {
+        &self.checks
+    }
+
+    /// Renames the columns of a parsed DataFrame that have a new name in the schema, in the order
+    /// of their names in the .dat files.
+    ///
+    /// A column keeps its name if the DataFrame already has a column with the new name, e.g. a
+    /// column unknown to the schema, and the conflict is added to `report`.
+    pub(crate) fn rename_columns(
+        &self,
+        dataframe: &mut DataFrame,
+        report: &mut ParseReport,
+    ) -> PolarsResult<()> {
+        for column in self.columns.values() {
+            let Some(rename) = &column.rename else {
+                continue;
+            };
+            if rename == &column.name || dataframe.get_column_index(&column.name).is_none() {
+                continue;
+            }
+            if dataframe.get_column_index(rename).is_some() {
+                report.issues.push(ParseIssue {
+                    location: Location::default(),
+                    action: IssueAction::Kept,
+                    reason: format!(
+                        "Column {} was not renamed to {}, which is already a column",
+                        column.name, rename
+                    ),
+                });
+                continue;
+            }
+            dataframe.rename(&column.name, PlSmallStr::from_str(rename))?;
+        }
+        Ok(())
+    }
+}
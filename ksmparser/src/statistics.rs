@@ -0,0 +1,178 @@
+/// Module for process capability statistics of measured values.
+///
+/// The statistics of a column are computed from its non-null values and the limits of the
+/// article, e.g. the `check_wall_min_minlimit`, `check_wall_min_nomlimit` and
+/// `check_wall_min_maxlimit` parameters for `wall_min`. Restricting the statistics to a time
+/// range is done by filtering the DataFrame before it is passed in.
+///
+/// Like the `compliance` module, only the minimum and maximum are limits, and the nominal value
+/// is the target. Articles usually only give a minimum and a nominal value, which leaves `cp`
+/// undefined and makes `cpk` the one-sided index of the minimum. Pass
+/// `Article::with_symmetric_limits` to take the tolerance to be symmetric around the nominal
+/// value instead.
+use super::article::{Article, Limits};
+use super::QueryError;
+use polars::prelude::*;
+use serde::Serialize;
+
+/// Columns evaluated by `article_capability` when no columns are given.
+pub const DEFAULT_COLUMNS: [&str; 2] = ["wall_min", "diameter_outer_mean"];
+
+/// Percentiles computed when no percentiles are given.
+pub const DEFAULT_PERCENTILES: [f64; 5] = [1.0, 5.0, 50.0, 95.0, 99.0];
+
+/// Value of a column at a percentile between 0 and 100.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Percentile {
+    pub percentile: f64,
+    pub value: f64,
+}
+
+/// Capability statistics of a measured column.
+///
+/// Statistics that cannot be computed, e.g. the standard deviation of a single value or `cp`
+/// without a maximum, are `None`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CapabilityStats {
+    /// Name of the measured column
+    pub column: String,
+    /// Number of non-null values
+    pub count: usize,
+    pub mean: Option<f64>,
+    /// Sample standard deviation
+    pub sigma: Option<f64>,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    /// Limits of the column taken from the article
+    pub limits: Limits,
+    /// `(max - min) / 6σ` of the limits, if both are given
+    pub cp: Option<f64>,
+    /// Distance from the mean to the nearest limit divided by 3σ. With a single limit this is
+    /// the one-sided index of that limit.
+    pub cpk: Option<f64>,
+    pub percentiles: Vec<Percentile>,
+}
+
+/// Computes the capability statistics of a column against the given limits.
+///
+/// # Arguments
+/// * `dataframe` - Measurement entries, already filtered to the time range of interest.
+/// * `column` - Name of the measured column.
+/// * `limits` - Limits of the column. The nominal value is not used.
+/// * `percentiles` - Percentiles between 0 and 100 to compute, interpolated linearly.
+///
+/// # Errors
+/// * `QueryError::UnknownColumn` if the DataFrame has no column named `column`.
+/// * `QueryError::ComputationError` if the column cannot be converted to floats.
+/// * `QueryError::InvalidArgument` if a percentile is outside 0 to 100.
+pub fn capability(
+    dataframe: &DataFrame,
+    column: &str,
+    limits: &Limits,
+    percentiles: &[f64],
+) -> Result<CapabilityStats, QueryError> {
+    if let Some(percentile) = percentiles.iter().find(|p| !(0.0..=100.0).contains(*p)) {
+        return Err(QueryError::InvalidArgument {
+            argument: "percentile".to_string(),
+            value: percentile.to_string(),
+            reason: "Outside 0 to 100".to_string(),
+        });
+    }
+    let values = dataframe
+        .column(column)
+        .map_err(|_| QueryError::UnknownColumn {
+            column: column.to_string(),
+        })?
+        .cast(&DataType::Float64)
+        .map_err(|source| QueryError::ComputationError { source })?;
+    let mut values: Vec<f64> = values
+        .f64()
+        .map_err(|source| QueryError::ComputationError { source })?
+        .into_iter()
+        .flatten()
+        .filter(|value| !value.is_nan())
+        .collect();
+    values.sort_by(f64::total_cmp);
+
+    let count = values.len();
+    let mean = (count > 0).then(|| values.iter().sum::<f64>() / count as f64);
+    let sigma = match mean {
+        Some(mean) if count > 1 => {
+            let squares: f64 = values.iter().map(|value| (value - mean).powi(2)).sum();
+            Some((squares / (count - 1) as f64).sqrt())
+        }
+        _ => None,
+    };
+
+    // Capability is undefined without spread
+    let (cp, cpk) = match (mean, sigma) {
+        (Some(mean), Some(sigma)) if sigma > 0.0 => {
+            let cp = match (limits.min, limits.max) {
+                (Some(min), Some(max)) => Some((max - min) / (6.0 * sigma)),
+                _ => None,
+            };
+            let lower = limits.min.map(|min| (mean - min) / (3.0 * sigma));
+            let upper = limits.max.map(|max| (max - mean) / (3.0 * sigma));
+            let cpk = match (lower, upper) {
+                (Some(lower), Some(upper)) => Some(lower.min(upper)),
+                (lower, upper) => lower.or(upper),
+            };
+            (cp, cpk)
+        }
+        _ => (None, None),
+    };
+
+    let percentiles = match count {
+        0 => Vec::new(),
+        _ => percentiles
+            .iter()
+            .map(|&percentile| Percentile {
+                percentile,
+                value: interpolate(&values, percentile),
+            })
+            .collect(),
+    };
+
+    Ok(CapabilityStats {
+        column: column.to_string(),
+        count,
+        mean,
+        sigma,
+        min: values.first().copied(),
+        max: values.last().copied(),
+        limits: *limits,
+        cp,
+        cpk,
+        percentiles,
+    })
+}
+
+/// Computes the capability statistics of several columns against the limits of an article.
+///
+/// Columns without limits in the article are evaluated with empty limits, so only `cp` and
+/// `cpk` are missing.
+///
+/// # Errors
+/// The errors of `capability` for the first column that fails.
+pub fn article_capability(
+    dataframe: &DataFrame,
+    article: &Article,
+    columns: &[&str],
+    percentiles: &[f64],
+) -> Result<Vec<CapabilityStats>, QueryError> {
+    columns
+        .iter()
+        .map(|column| {
+            let limits = article.limits.get(*column).copied().unwrap_or_default();
+            capability(dataframe, column, &limits, percentiles)
+        })
+        .collect()
+}
+
+/// Returns the value at `percentile` of sorted, non-empty `values`, interpolating linearly
+/// between the closest ranks.
+fn interpolate(values: &[f64], percentile: f64) -> f64 {
+    let rank = percentile / 100.0 * (values.len() - 1) as f64;
+    let (lower, upper) = (rank.floor() as usize, rank.ceil() as usize);
+    values[lower] + (values[upper] - values[lower]) * (rank - lower as f64)
+}
@@ -1,16 +1,37 @@
-use ksmparser::{article, measurement, ParseError};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use ksmparser::aggregate::Aggregation;
+use ksmparser::article::{Article, Limits};
+use ksmparser::checkresult::{CheckFlag, CheckResultMapping};
+use ksmparser::discovery::Discovery;
+use ksmparser::key::KsmFileKey;
+use ksmparser::measurement::{DatCursor, DatUpdate};
+use ksmparser::schema::ColumnSchema;
+use ksmparser::{
+    aggregate, archive, article, compliance, filter, measurement, statistics, DuplicatePolicy,
+    IssueAction, ParseError, ParseOptions, QueryError, TextEncoding, Timezone,
+};
+use polars::prelude::{df, Column, DataFrame, DataType, IntoLazy, TimeUnit};
+use std::error::Error;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use zip::write::SimpleFileOptions;
+use zip::ZipWriter;
 
 #[test]
 fn parse_article_invalid_filename() {
-    let result = article::parse_art_file("invalid_file_name.abc");
-    assert!(matches!(result, Err(ParseError::InvalidFile(_))));
+    let result = article::parse_art_file("invalid_file_name.abc", &ParseOptions::default());
+    assert!(matches!(result, Err(ParseError::InvalidFile { .. })));
 }
 
 #[test]
 fn parse_missing_none_article() {
-    let result = article::parse_art_file("testdata/missing_none.art");
+    let result = article::parse_art_file("testdata/missing_none.art", &ParseOptions::default());
     assert!(
-        matches!(result, Err(ParseError::MissingField(_))),
+        matches!(result, Err(ParseError::MissingField { .. })),
         "Did not return ParseError::MissingField. Result: {:?}",
         result
     );
@@ -18,7 +39,7 @@ fn parse_missing_none_article() {
 
 #[test]
 fn parse_valid_article_parameters() {
-    match article::parse_art_file("testdata/valid.art") {
+    match article::parse_art_file("testdata/valid.art", &ParseOptions::default()) {
         Ok(result) => {
             assert_eq!(result.height(), 1);
             assert_eq!(
@@ -32,24 +53,17 @@ fn parse_valid_article_parameters() {
                 "round_local"
             );
             assert_eq!(
-                result
-                    .column("cable_parts")
-                    .unwrap()
-                    .str()
-                    .unwrap()
-                    .get(0)
-                    .unwrap_or_default(),
-                "6"
+                result.column("cable_parts").unwrap().i64().unwrap().get(0),
+                Some(6)
             );
             assert_eq!(
                 result
                     .column("check_wall_min_nomlimit")
                     .unwrap()
-                    .str()
+                    .f64()
                     .unwrap()
-                    .get(0)
-                    .unwrap_or_default(),
-                "0.13"
+                    .get(0),
+                Some(0.13)
             );
             assert_eq!(
                 result
@@ -83,52 +97,1423 @@ fn parse_valid_article_parameters() {
             );
         }
         Err(e) => {
-            assert!(false, "parse_art_file returned Err({})", e);
+            panic!("parse_art_file returned Err({})", e);
         }
     }
 }
 
+#[test]
+fn parse_numeric_article_parameters() {
+    let result = article::parse_art_bytes(
+        b"round_local\nNone\ncheck_wall_min_minlimit = 0,25\ncheck_wall_min_nomlimit = 0.3\ncheck_user2_maxlimit = \ninfo1 = FKUX 105 0,25\n",
+        &ParseOptions::default(),
+    )
+    .unwrap();
+    let limit = |column: &str| result.column(column).unwrap().f64().unwrap().get(0);
+    assert_eq!(limit("check_wall_min_minlimit"), Some(0.25));
+    assert_eq!(limit("check_wall_min_nomlimit"), Some(0.3));
+    assert_eq!(limit("check_user2_maxlimit"), None);
+    assert_eq!(
+        result.column("info1").unwrap().str().unwrap().get(0),
+        Some("FKUX 105 0,25")
+    );
+
+    let error = article::parse_art_bytes(
+        b"round_local\nNone\ninfo6 = 202\ncheck_wall_min_minlimit = thin\n",
+        &ParseOptions::default(),
+    )
+    .unwrap_err();
+    match &error {
+        ParseError::TypeConversionError { column, value, .. } => {
+            assert_eq!(column, "check_wall_min_minlimit");
+            assert_eq!(value, "thin");
+        }
+        _ => panic!("Should return TypeConversionError, got {}", error),
+    }
+    assert_eq!(error.location().unwrap().line, Some(4));
+}
+
+#[test]
+fn convert_typed_article() {
+    let dataframe =
+        article::parse_art_file("testdata/valid.art", &ParseOptions::default()).unwrap();
+    let article = Article::from_dataframe(&dataframe).unwrap();
+    assert_eq!(article.pgm_name, "round_local");
+    assert_eq!(article.info2.as_deref(), Some(""));
+    assert_eq!(article.info6.as_deref(), Some("202"));
+    assert_eq!(article.material_core.as_deref(), Some("Cu"));
+    assert_eq!(article.cable_parts, Some(6));
+    assert_eq!(
+        article.limits["wall_min"],
+        Limits {
+            min: Some(0.1),
+            nom: Some(0.13),
+            max: None
+        }
+    );
+    assert_eq!(article.limits["user2"].max, Some(7.98));
+    assert!(article.other.is_empty());
+
+    // Converting back gives the parsed DataFrame
+    let converted = article.to_dataframe().unwrap();
+    let converted = converted
+        .select(dataframe.get_column_names_owned())
+        .unwrap();
+    assert!(converted.equals_missing(&dataframe));
+
+    let json = serde_json::to_string(&article).unwrap();
+    assert_eq!(serde_json::from_str::<Article>(&json).unwrap(), article);
+
+    let dataframe = df!("pgm_name" => ["round_local"], "cable_parts" => ["six"]).unwrap();
+    let result = Article::from_dataframe(&dataframe);
+    assert!(matches!(
+        result,
+        Err(ParseError::TypeConversionError { .. })
+    ));
+}
+
+#[test]
+fn evaluate_measurement_limits() {
+    let options = ParseOptions::default();
+    let mut measurements = measurement::parse_dat_file("testdata/valid.dat", &options).unwrap();
+    measurements
+        .with_column(Column::new(
+            "diameter_outer_mean".into(),
+            [1.04, 1.05, 1.07, 1.05],
+        ))
+        .unwrap();
+    let dataframe = article::parse_art_file("testdata/valid.art", &options).unwrap();
+    let mut article = Article::from_dataframe(&dataframe).unwrap();
+
+    // Measured wall_min values are 0.21, 0.22, 0.19 and 0.20
+    article.limits.get_mut("wall_min").unwrap().min = Some(0.2);
+    article.limits.get_mut("wall_min").unwrap().max = Some(0.215);
+    let result = compliance::evaluate_limits(&measurements, &article).unwrap();
+    assert_eq!(
+        result
+            .column("wall_min_pass")
+            .unwrap()
+            .bool()
+            .unwrap()
+            .into_iter()
+            .collect::<Vec<_>>(),
+        [Some(true), Some(false), Some(false), Some(true)]
+    );
+    assert_eq!(
+        result
+            .column("limit_violation")
+            .unwrap()
+            .str()
+            .unwrap()
+            .into_iter()
+            .collect::<Vec<_>>(),
+        [
+            None,
+            Some("wall_min: 0.22 is above the maximum 0.215"),
+            Some("wall_min: 0.19 is below the minimum 0.2"),
+            None
+        ]
+    );
+    // Values without a measured column are not checked
+    assert!(result.column("wall_mean_pass").is_err());
+    // Values with only a nominal value are reported as unchecked
+    assert_eq!(
+        article.limits["diameter_outer_mean"],
+        Limits {
+            min: None,
+            nom: Some(1.05),
+            max: None
+        }
+    );
+    assert_eq!(
+        result
+            .column("diameter_outer_mean_pass")
+            .unwrap()
+            .null_count(),
+        measurements.height()
+    );
+    assert_eq!(result.width(), measurements.width() + 3);
+
+    // A minimum is only mirrored to a maximum when a symmetric tolerance is asked for
+    article.limits.get_mut("diameter_outer_mean").unwrap().min = Some(1.04);
+    let diameter_pass = |article: &Article| {
+        compliance::evaluate_limits(&measurements, article)
+            .unwrap()
+            .column("diameter_outer_mean_pass")
+            .unwrap()
+            .bool()
+            .unwrap()
+            .into_iter()
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(diameter_pass(&article), [Some(true); 4]);
+    assert_eq!(
+        diameter_pass(&article.with_symmetric_limits()),
+        [Some(true), Some(true), Some(false), Some(true)]
+    );
+}
+
+#[test]
+fn aggregate_measurements_over_time() {
+    // Measured at 07:13:20 to 07:16:20 on 2023-11-15 in Tokyo, the previous day in UTC
+    let options = ParseOptions {
+        timezone: "Asia/Tokyo".parse().unwrap(),
+        ..ParseOptions::default()
+    };
+    let measurements = measurement::parse_dat_file("testdata/valid.dat", &options).unwrap();
+    let aggregations = [Aggregation::Mean, Aggregation::Count];
+    let values = |df: &DataFrame, column: &str| -> Vec<i64> {
+        let column = df.column(column).unwrap().cast(&DataType::Int64).unwrap();
+        column.i64().unwrap().into_iter().flatten().collect()
+    };
+
+    // Daily buckets start at local midnight
+    let result = aggregate::aggregate(
+        measurements.clone().lazy(),
+        "1d",
+        "0h",
+        &["wall_min"],
+        &aggregations,
+    )
+    .unwrap();
+    assert_eq!(values(&result, "local_time"), vec![1_699_974_000_000]);
+    assert_eq!(values(&result, "wall_min_count"), vec![4]);
+    let mean = result
+        .column("wall_min_mean")
+        .unwrap()
+        .f64()
+        .unwrap()
+        .get(0);
+    assert!((mean.unwrap() - 0.205).abs() < 1e-12);
+
+    let result = aggregate::aggregate(
+        measurements.clone().lazy(),
+        "2m",
+        "0h",
+        &["wall_min"],
+        &aggregations,
+    )
+    .unwrap();
+    assert_eq!(values(&result, "wall_min_count"), vec![1, 2, 1]);
+
+    // Invalid requests are reported as such, not as malformed data
+    let error = aggregate::aggregate(measurements.clone().lazy(), "1x", "0h", &[], &aggregations)
+        .unwrap_err();
+    assert!(matches!(error, QueryError::InvalidArgument { .. }));
+    assert_eq!(
+        error.to_string(),
+        "Invalid bucket length '1x': Not a duration such as 1h or 1d"
+    );
+    assert!(matches!(
+        aggregate::aggregate(measurements.clone().lazy(), "-1h", "0h", &[], &aggregations),
+        Err(QueryError::InvalidArgument { .. })
+    ));
+    assert!(matches!(
+        aggregate::aggregate(measurements.clone().lazy(), "1h", "0h", &["info5"], &[]),
+        Err(QueryError::InvalidArgument { .. })
+    ));
+    assert!(matches!(
+        aggregate::aggregate(measurements.clone().lazy(), "1h", "0h", &["ovality"], &[]),
+        Err(QueryError::UnknownColumn { .. })
+    ));
+    let without_time = measurements.drop(aggregate::TIME_COLUMN).unwrap();
+    let error =
+        aggregate::aggregate(without_time.lazy(), "1h", "0h", &["wall_min"], &[]).unwrap_err();
+    assert!(error.is_request_error());
+    assert_eq!(error.to_string(), "Unknown column: local_time");
+    assert!(matches!(
+        "mode".parse::<Aggregation>(),
+        Err(QueryError::UnknownAggregation { .. })
+    ));
+}
+
+#[test]
+fn filter_measurement_rows() {
+    let measurements =
+        measurement::parse_dat_file("testdata/valid.dat", &ParseOptions::default()).unwrap();
+    let schema = measurements.schema();
+    let wall_min = |expression: &str| -> Vec<f64> {
+        let expr = filter::parse_filter(expression, schema).unwrap();
+        let result = measurements.clone().lazy().filter(expr).collect().unwrap();
+        let column = result.column("wall_min").unwrap().f64().unwrap().clone();
+        column.into_iter().flatten().collect()
+    };
+
+    assert_eq!(wall_min("wall_min < 0.2"), vec![0.19]);
+    assert_eq!(
+        wall_min(r#"wall_min <= 0.2 and info5 == "Bo""#),
+        vec![0.19, 0.2]
+    );
+    assert_eq!(
+        wall_min("not (info5 = 'Bo') or measure_time1970 >= 1700000180"),
+        vec![0.21, 0.22, 0.2]
+    );
+    assert_eq!(wall_min("wall_min is not null AND wall_min > 1").len(), 0);
+
+    // Unknown columns and mismatching types are rejected with the position of the problem
+    let error = filter::parse_filter("nope > 1", schema).unwrap_err();
+    assert!(matches!(
+        error,
+        QueryError::InvalidFilter { position: 0, .. }
+    ));
+    let error = filter::parse_filter("info5 == 3", schema).unwrap_err();
+    assert!(matches!(
+        error,
+        QueryError::InvalidFilter { position: 9, .. }
+    ));
+    assert!(error
+        .to_string()
+        .contains("cannot be compared with number 3"));
+    for invalid in [
+        "wall_min <",
+        "(wall_min > 0.2",
+        "wall_min > 0.2 0.3",
+        "info5 == \"Bo",
+    ] {
+        assert!(matches!(
+            filter::parse_filter(invalid, schema),
+            Err(QueryError::InvalidFilter { .. })
+        ));
+    }
+    // Deeply nested filters are rejected instead of overflowing the stack
+    let nested = |depth: usize| format!("{}wall_min > 0.2{}", "(".repeat(depth), ")".repeat(depth));
+    assert!(filter::parse_filter(&nested(filter::MAX_NESTING), schema).is_ok());
+    let error = filter::parse_filter(&nested(filter::MAX_NESTING + 1), schema).unwrap_err();
+    assert!(matches!(
+        error,
+        QueryError::InvalidFilter { position: 64, .. }
+    ));
+    for deep in ["(".repeat(300), "not ".repeat(300) + "wall_min > 0.2"] {
+        assert!(matches!(
+            filter::parse_filter(&deep, schema),
+            Err(QueryError::InvalidFilter { .. })
+        ));
+    }
+}
+
+#[test]
+fn compute_capability_statistics() {
+    let options = ParseOptions::default();
+    let measurements = measurement::parse_dat_file("testdata/valid.dat", &options).unwrap();
+    let dataframe = article::parse_art_file("testdata/valid.art", &options).unwrap();
+    let mut article = Article::from_dataframe(&dataframe).unwrap();
+
+    // Measured wall_min values are 0.21, 0.22, 0.19 and 0.20 with a minimum of 0.1 and a
+    // nominal value of 0.13
+    let stats =
+        statistics::article_capability(&measurements, &article, &["wall_min"], &[0.0, 50.0, 100.0])
+            .unwrap();
+    let sigma = (0.0005f64 / 3.0).sqrt();
+    assert_eq!(stats[0].count, 4);
+    assert!((stats[0].mean.unwrap() - 0.205).abs() < 1e-12);
+    assert!((stats[0].sigma.unwrap() - sigma).abs() < 1e-12);
+    assert_eq!(stats[0].cp, None, "The nominal value is not a limit");
+    assert!((stats[0].cpk.unwrap() - 0.105 / (3.0 * sigma)).abs() < 1e-9);
+    let percentiles: Vec<f64> = stats[0].percentiles.iter().map(|p| p.value).collect();
+    assert!((percentiles[1] - 0.205).abs() < 1e-12);
+    assert_eq!((percentiles[0], percentiles[2]), (0.19, 0.22));
+
+    // A symmetric tolerance around the nominal value has to be asked for
+    let symmetric = article.with_symmetric_limits();
+    assert!((symmetric.limits["wall_min"].max.unwrap() - 0.16).abs() < 1e-12);
+    let stats =
+        statistics::article_capability(&measurements, &symmetric, &["wall_min"], &[]).unwrap();
+    assert!((stats[0].cp.unwrap() - 0.06 / (6.0 * sigma)).abs() < 1e-9);
+    assert!((stats[0].cpk.unwrap() + 0.045 / (3.0 * sigma)).abs() < 1e-9);
+
+    // With both limits cpk is taken from the nearest one
+    article.limits.get_mut("wall_min").unwrap().max = Some(0.25);
+    let stats =
+        statistics::article_capability(&measurements, &article, &["wall_min"], &[]).unwrap();
+    assert!((stats[0].cp.unwrap() - 0.15 / (6.0 * sigma)).abs() < 1e-9);
+    assert!((stats[0].cpk.unwrap() - 0.045 / (3.0 * sigma)).abs() < 1e-9);
+
+    // Without a minimum or maximum there is no capability
+    article.limits.get_mut("wall_min").unwrap().min = None;
+    article.limits.get_mut("wall_min").unwrap().max = None;
+    let stats =
+        statistics::article_capability(&measurements, &article, &["wall_min"], &[]).unwrap();
+    assert_eq!((stats[0].cp, stats[0].cpk), (None, None));
+
+    let result = statistics::article_capability(&measurements, &article, &["ovality"], &[]);
+    assert!(matches!(result, Err(QueryError::UnknownColumn { .. })));
+    let result = statistics::article_capability(&measurements, &article, &["wall_min"], &[101.0]);
+    assert!(matches!(result, Err(QueryError::InvalidArgument { .. })));
+}
+
+#[test]
+fn parse_article_from_bytes() {
+    let result = article::parse_art_bytes(
+        b"round_local\nNone\ninfo6 = 202\ninfo5 = \xc5sa\n",
+        &ParseOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(result.width(), 3);
+    assert_eq!(
+        result.column("info5").unwrap().str().unwrap().get(0),
+        Some("Åsa")
+    );
+
+    let result = article::parse_art_bytes(b"round_local\n", &ParseOptions::default());
+    assert!(matches!(result, Err(ParseError::MissingField { .. })));
+}
+
+#[test]
+fn parse_measurement_data_from_reader() {
+    let file = fs::File::open("testdata/valid.dat").unwrap();
+    let from_reader = measurement::parse_dat_reader(file, &ParseOptions::default()).unwrap();
+    let from_file =
+        measurement::parse_dat_file("testdata/valid.dat", &ParseOptions::default()).unwrap();
+    assert!(from_reader.equals_missing(&from_file));
+
+    let result = measurement::parse_dat_bytes(b"\"measure_time1970\"\n", &ParseOptions::default());
+    assert!(matches!(result, Err(ParseError::MalformedEntry { .. })));
+}
+
 #[test]
 fn parse_valid_measurement_data() {
-    match measurement::parse_dat_file("testdata/valid.dat") {
+    match measurement::parse_dat_file("testdata/valid.dat", &ParseOptions::default()) {
         Ok(result) => {
             assert_eq!(result.width(), 4, "Wrong number of columns");
             assert_eq!(result.height(), 4, "Wrong number of rows");
         }
         Err(e) => {
-            assert!(false, "Error while parsing: {}", e);
+            panic!("Error while parsing: {}", e);
         }
     }
 }
 
 #[test]
 fn parse_uneven_rows_measurement_data() {
-    match measurement::parse_dat_file("testdata/uneven_row.dat") {
+    match measurement::parse_dat_file("testdata/uneven_row.dat", &ParseOptions::default()) {
         Ok(_) => {
-            assert!(false, "Should return an error");
+            panic!("Should return an error");
         }
         Err(e) => match e {
-            ParseError::MalformedEntry { .. } => return,
-            _ => assert!(false, "Should return MalformedEntry"),
+            ParseError::MalformedEntry { .. } => (),
+            _ => panic!("Should return MalformedEntry"),
         },
     }
 }
 
 #[test]
 fn parse_uneven_col_measurement_data() {
-    match measurement::parse_dat_file("testdata/uneven_col.dat") {
+    match measurement::parse_dat_file("testdata/uneven_col.dat", &ParseOptions::default()) {
         Ok(_) => {
-            assert!(false, "Should return an error");
+            panic!("Should return an error");
         }
         Err(e) => match e {
-            ParseError::MalformedEntry { .. } => return,
-            _ => assert!(false, "Should return MalformedEntry"),
+            ParseError::MalformedEntry { .. } => (),
+            _ => panic!("Should return MalformedEntry"),
         },
     }
 }
 
+#[test]
+fn parse_errors_report_file_and_line() {
+    let error = measurement::parse_dat_file("testdata/uneven_col.dat", &ParseOptions::default())
+        .unwrap_err();
+    let location = error.location().unwrap();
+    assert_eq!(
+        location.path.as_deref(),
+        Some("testdata/uneven_col.dat".as_ref())
+    );
+    assert_eq!(location.line, Some(4));
+    assert!(error.to_string().starts_with("testdata/uneven_col.dat:4: "));
+
+    let error = measurement::parse_dat_file("testdata/uneven_row.dat", &ParseOptions::default())
+        .unwrap_err();
+    assert_eq!(error.location().unwrap().line, Some(3));
+
+    let error =
+        article::parse_art_file("testdata/missing_none.art", &ParseOptions::default()).unwrap_err();
+    assert_eq!(error.location().unwrap().line, Some(2));
+
+    // Errors can be boxed and expose the underlying IO error
+    let boxed: Box<dyn Error> =
+        article::parse_art_file("invalid_file_name.abc", &ParseOptions::default())
+            .unwrap_err()
+            .into();
+    let source = boxed.source().unwrap();
+    assert_eq!(
+        source.downcast_ref::<std::io::Error>().unwrap().kind(),
+        std::io::ErrorKind::NotFound
+    );
+
+    // The message of the source is only repeated by the alternate format
+    let source_message = source.to_string();
+    assert!(!boxed.to_string().contains(&source_message));
+    assert!(format!("{:#}", boxed).ends_with(&format!(": {}", source_message)));
+}
+
+#[test]
+fn parse_appended_errors_report_file_line() {
+    let path = temporary_dat_copy("appended_error");
+    let options = ParseOptions::default();
+    let (_, cursor, _) =
+        measurement::parse_dat_file_from(&path, &DatCursor::default(), &options).unwrap();
+
+    let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+    file.write_all(b"\"measure_time1970\"\t\"wall_min\"\n1700000240\n")
+        .unwrap();
+
+    let error = measurement::parse_dat_file_from(&path, &cursor, &options).unwrap_err();
+    assert_eq!(error.location().unwrap().line, Some(11));
+    assert_eq!(error.location().unwrap().path.as_ref(), Some(&path));
+
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn parse_lenient_measurement_data() {
+    let options = ParseOptions {
+        lenient: true,
+        ..ParseOptions::default()
+    };
+
+    let (dataframe, report) =
+        measurement::parse_dat_file_with_report("testdata/uneven_col.dat", &options).unwrap();
+    assert_eq!(dataframe.height(), 1);
+    assert_eq!(report.issues.len(), 1);
+    let issue = &report.issues[0];
+    assert_eq!(issue.action, IssueAction::Skipped);
+    assert_eq!(issue.location.line, Some(4));
+    assert_eq!(
+        issue.location.path.as_deref(),
+        Some("testdata/uneven_col.dat".as_ref())
+    );
+
+    let (dataframe, report) =
+        measurement::parse_dat_file_with_report("testdata/uneven_row.dat", &options).unwrap();
+    assert_eq!(dataframe.height(), 1);
+    assert_eq!(report.issues[0].location.line, Some(3));
+
+    // Unparsable values are stored as null in both modes
+    let content = b"\"measure_time1970\"\t\"wall_min\"\n1700000000\tabc\n";
+    let (dataframe, report) =
+        measurement::parse_dat_reader_with_report(&content[..], &ParseOptions::default()).unwrap();
+    assert_eq!(dataframe.column("wall_min").unwrap().null_count(), 1);
+    assert_eq!(report.issues.len(), 1);
+    assert_eq!(report.issues[0].action, IssueAction::Coerced);
+    assert_eq!(report.issues[0].location.line, Some(2));
+
+    // Integer columns are converted to floats to hold fractional values of later entries
+    let schema =
+        ColumnSchema::from_toml("[[columns]]\nname = \"user1\"\ndtype = \"i64\"\n").unwrap();
+    let integer_options = ParseOptions {
+        schema: Arc::new(schema),
+        ..ParseOptions::default()
+    };
+    let content = b"\"measure_time1970\"\t\"user1\"\n1700000000\t3\n\
+        \"measure_time1970\"\t\"user1\"\n1700000060\t0.25\n";
+    let (dataframe, report) =
+        measurement::parse_dat_reader_with_report(&content[..], &integer_options).unwrap();
+    let user1 = dataframe.column("user1").unwrap();
+    assert_eq!(user1.dtype(), &DataType::Float64);
+    assert_eq!(
+        user1.f64().unwrap().into_iter().collect::<Vec<_>>(),
+        [Some(3.0), Some(0.25)]
+    );
+    assert_eq!(report.issues.len(), 1);
+    assert_eq!(report.issues[0].action, IssueAction::Promoted);
+    assert_eq!(report.issues[0].location.line, Some(4));
+
+    // Numbers that are not finite do not convert the column
+    let content = b"\"measure_time1970\"\t\"user1\"\n1700000000\t3\n\
+        \"measure_time1970\"\t\"user1\"\n1700000060\tNaN\n\
+        \"measure_time1970\"\t\"user1\"\n1700000120\tinf\n";
+    let lenient_integer_options = ParseOptions {
+        lenient: true,
+        ..integer_options
+    };
+    let (dataframe, report) =
+        measurement::parse_dat_reader_with_report(&content[..], &lenient_integer_options).unwrap();
+    let user1 = dataframe.column("user1").unwrap();
+    assert_eq!(user1.dtype(), &DataType::Int64);
+    assert_eq!(
+        user1.i64().unwrap().into_iter().collect::<Vec<_>>(),
+        [Some(3), None, None]
+    );
+    assert_eq!(report.issues.len(), 2);
+    assert_eq!(report.issues[0].action, IssueAction::Coerced);
+
+    let (_, report) =
+        measurement::parse_dat_file_with_report("testdata/valid.dat", &options).unwrap();
+    assert!(report.is_empty());
+}
+
+#[test]
+fn parse_lenient_measurement_data_missing_value_row() {
+    let content = b"\"measure_time1970\"\t\"wall_min\"\t\"info5\"\n1700000000\t0.21\tAnna\n\
+        \"measure_time1970\"\t\"wall_min\"\t\"info5\"\n\
+        \"measure_time1970\"\t\"wall_min\"\t\"info5\"\n1700000120\t0.19\tBo\n\
+        \"measure_time1970\"\t\"wall_min\"\t\"info5\"\n1700000180\t0.2\tBo\n";
+    let options = ParseOptions {
+        lenient: true,
+        ..ParseOptions::default()
+    };
+
+    // The entry without values is skipped and the following entries stay aligned
+    let (dataframe, report) =
+        measurement::parse_dat_reader_with_report(&content[..], &options).unwrap();
+    assert_eq!(dataframe.height(), 3);
+    let info5: Vec<Option<&str>> = dataframe
+        .column("info5")
+        .unwrap()
+        .str()
+        .unwrap()
+        .into_iter()
+        .collect();
+    assert_eq!(info5, [Some("Anna"), Some("Bo"), Some("Bo")]);
+    assert_eq!(report.issues.len(), 1);
+    assert_eq!(report.issues[0].action, IssueAction::Skipped);
+    assert_eq!(report.issues[0].location.line, Some(3));
+
+    // Strict mode pairs the lines by position, leaving the last column row without values
+    let error = measurement::parse_dat_reader_with_report(&content[..], &ParseOptions::default())
+        .unwrap_err();
+    assert!(matches!(error, ParseError::MalformedEntry { .. }));
+    assert_eq!(error.location().unwrap().line, Some(7));
+}
+
+#[test]
+fn parse_measurement_data_after_empty_line() {
+    let content = b"\"measure_time1970\"\t\"wall_min\"\n1700000000\t0.21\n\n\
+        \"measure_time1970\"\t\"wall_min\"\n1700000060\t0.22\n";
+
+    // Strict mode stops at an empty line
+    let (dataframe, report) =
+        measurement::parse_dat_reader_with_report(&content[..], &ParseOptions::default()).unwrap();
+    assert_eq!(dataframe.height(), 1);
+    assert!(report.is_empty());
+
+    // Lenient mode skips it and reads the following entries
+    let options = ParseOptions {
+        lenient: true,
+        ..ParseOptions::default()
+    };
+    let (dataframe, report) =
+        measurement::parse_dat_reader_with_report(&content[..], &options).unwrap();
+    assert_eq!(dataframe.height(), 2);
+    assert!(report.is_empty());
+}
+
+#[test]
+fn parse_duplicate_columns() {
+    let content = b"\"measure_time1970\"\t\"wall_min\"\n1700000000\t0.21\n\
+        \"measure_time1970\"\t\"wall_min\"\t\"wall_min\"\t\"wall_min_2\"\n1700000060\t0.22\t0.23\t0.24\n";
+    let parse = |duplicates| {
+        let options = ParseOptions {
+            duplicates,
+            ..ParseOptions::default()
+        };
+        measurement::parse_dat_reader_with_report(&content[..], &options).unwrap()
+    };
+    let wall_min = |dataframe: &DataFrame, column: &str| {
+        dataframe
+            .column(column)
+            .unwrap()
+            .f64()
+            .unwrap()
+            .into_iter()
+            .collect::<Vec<_>>()
+    };
+
+    // Rejected entries fail the file, or are left out when parsing leniently
+    let error = measurement::parse_dat_reader_with_report(&content[..], &ParseOptions::default())
+        .unwrap_err();
+    assert!(matches!(error, ParseError::DuplicateColumns { .. }));
+    assert_eq!(error.location().unwrap().line, Some(3));
+    let lenient = ParseOptions {
+        lenient: true,
+        ..ParseOptions::default()
+    };
+    let (dataframe, report) =
+        measurement::parse_dat_reader_with_report(&content[..], &lenient).unwrap();
+    assert_eq!(dataframe.height(), 1);
+    assert_eq!(report.issues[0].action, IssueAction::Skipped);
+    assert_eq!(report.issues[0].location.line, Some(3));
+
+    let (dataframe, report) = parse(DuplicatePolicy::KeepFirst);
+    assert_eq!(wall_min(&dataframe, "wall_min"), [Some(0.21), Some(0.22)]);
+    assert_eq!(report.issues.len(), 1);
+    assert_eq!(report.issues[0].action, IssueAction::Discarded);
+    assert_eq!(report.issues[0].location.line, Some(3));
+
+    let (dataframe, report) = parse(DuplicatePolicy::KeepLast);
+    assert_eq!(wall_min(&dataframe, "wall_min"), [Some(0.21), Some(0.23)]);
+    assert!(report.issues[0].reason.contains("'0.22'"));
+
+    // Renamed columns skip suffixes that are already taken
+    let (dataframe, report) = parse(DuplicatePolicy::Rename);
+    assert_eq!(wall_min(&dataframe, "wall_min_3"), [None, Some(0.23)]);
+    assert_eq!(report.issues[0].action, IssueAction::Renamed);
+
+    assert_eq!(
+        "Last".parse::<DuplicatePolicy>().unwrap(),
+        DuplicatePolicy::KeepLast
+    );
+    assert!(matches!(
+        "newest".parse::<DuplicatePolicy>(),
+        Err(ParseError::UnknownDuplicatePolicy { .. })
+    ));
+}
+
+#[test]
+fn parse_with_column_schema() {
+    let schema = ColumnSchema::from_file("testdata/schema.toml").unwrap();
+    assert_eq!(
+        schema,
+        ColumnSchema::from_file("testdata/schema.json").unwrap()
+    );
+    assert_eq!(schema.unit("wall_min"), Some("mm"));
+    assert_eq!(schema.data_type("user3"), DataType::Float64);
+    // Built-in columns are kept
+    assert_eq!(schema.data_type("measure_time1970"), DataType::Int64);
+
+    let options = ParseOptions {
+        schema: Arc::new(schema),
+        ..ParseOptions::default()
+    };
+    let result = measurement::parse_dat_file("testdata/valid.dat", &options).unwrap();
+    assert!(result.column("info5").is_err());
+    assert_eq!(
+        result.column("operator").unwrap().str().unwrap().get(0),
+        Some("Anna")
+    );
+
+    let result = measurement::parse_dat_bytes(
+        b"\"measure_time1970\"\t\"user3\"\n1700000000\t12.5\n",
+        &options,
+    )
+    .unwrap();
+    assert_eq!(
+        result.column("user3").unwrap().f64().unwrap().get(0),
+        Some(12.5)
+    );
+
+    let error = ColumnSchema::from_file("testdata/invalid_schema.toml").unwrap_err();
+    assert!(matches!(error, ParseError::InvalidSchema { .. }));
+    assert!(error
+        .to_string()
+        .starts_with("testdata/invalid_schema.toml: "));
+}
+
+#[test]
+fn validate_column_schema_renames() {
+    let invalid_renames = [
+        // The time columns are used by the endpoints
+        r#"{"columns": [{"name": "measure_time1970", "dtype": "i64", "rename": "time"}]}"#,
+        r#"{"columns": [{"name": "info5", "dtype": "str", "rename": "local_time"}]}"#,
+        r#"{"columns": [{"name": "info5", "dtype": "str", "rename": "check_wall_min_failed"}],
+            "checks": [{"name": "wall_min", "bit": 0}]}"#,
+        // New names must not collide
+        r#"{"columns": [{"name": "info5", "dtype": "str", "rename": "wall_min"}]}"#,
+        r#"{"columns": [{"name": "info4", "dtype": "str", "rename": "operator"},
+            {"name": "info5", "dtype": "str", "rename": "operator"}]}"#,
+    ];
+    for content in invalid_renames {
+        let error = ColumnSchema::from_json(content).unwrap_err();
+        assert!(
+            matches!(error, ParseError::InvalidSchema { .. }),
+            "{} should be rejected",
+            content
+        );
+    }
+
+    // A column unknown to the schema can still hold the new name, so the column keeps its name
+    let schema = ColumnSchema::from_file("testdata/schema.toml").unwrap();
+    let options = ParseOptions {
+        schema: Arc::new(schema),
+        ..ParseOptions::default()
+    };
+    let (dataframe, report) = measurement::parse_dat_reader_with_report(
+        &b"\"measure_time1970\"\t\"info5\"\t\"operator\"\n1700000000\tAnna\tBo\n"[..],
+        &options,
+    )
+    .unwrap();
+    assert_eq!(
+        dataframe.column("info5").unwrap().str().unwrap().get(0),
+        Some("Anna")
+    );
+    assert_eq!(report.issues.len(), 1);
+    assert_eq!(report.issues[0].action, IssueAction::Kept);
+}
+
+#[test]
+fn decode_checkresult_flags() {
+    let content = b"\"measure_time1970\"\t\"checkresult\"\n1700000000\t0\n\
+        \"measure_time1970\"\t\"checkresult\"\n1700000060\t5\n\
+        \"measure_time1970\"\t\"checkresult\"\n1700000120\tOK\n";
+    let failed = |dataframe: &DataFrame, check: &str| {
+        dataframe
+            .column(&CheckResultMapping::column_name(check))
+            .unwrap()
+            .bool()
+            .unwrap()
+            .into_iter()
+            .collect::<Vec<_>>()
+    };
+
+    // Without a mapping in the schema no check columns are added
+    let result = measurement::parse_dat_bytes(content, &ParseOptions::default()).unwrap();
+    assert!(ParseOptions::default().schema.checks().checks().is_empty());
+    assert!(result.column("check_wall_min_failed").is_err());
+
+    let schema = ColumnSchema::from_toml(
+        "[[checks]]\nname = \"wall_min\"\nbit = 0\n\n\
+        [[checks]]\nname = \"wall_mean\"\nbit = 1\n\n\
+        [[checks]]\nname = \"ovality\"\nbit = 2\n",
+    )
+    .unwrap();
+    assert_eq!(schema.checks().checks().len(), 3);
+    let options = ParseOptions {
+        schema: Arc::new(schema),
+        ..ParseOptions::default()
+    };
+    let result = measurement::parse_dat_bytes(content, &options).unwrap();
+    assert_eq!(failed(&result, "wall_min"), [Some(false), Some(true), None]);
+    assert_eq!(
+        failed(&result, "wall_mean"),
+        [Some(false), Some(false), None]
+    );
+    assert_eq!(failed(&result, "ovality"), [Some(false), Some(true), None]);
+    // The original value is kept
+    assert_eq!(
+        result.column("checkresult").unwrap().str().unwrap().get(1),
+        Some("5")
+    );
+
+    let mapping = CheckResultMapping::new(vec![
+        CheckFlag::new("wall_min", 0),
+        CheckFlag::new("ovality", 3),
+    ])
+    .unwrap();
+    let outcomes = mapping.decode("0x8").unwrap();
+    assert!(outcomes
+        .iter()
+        .all(|outcome| outcome.failed == (outcome.name == "ovality")));
+
+    let error = ColumnSchema::from_json(r#"{"checks": [{"name": "wall_min", "bit": 64}]}"#);
+    assert!(matches!(error, Err(ParseError::InvalidSchema { .. })));
+}
+
+#[test]
+fn parse_with_configured_timezone() {
+    let local_time = |options: &ParseOptions| {
+        let result = measurement::parse_dat_file("testdata/valid.dat", options).unwrap();
+        result.column("local_time").unwrap().dtype().clone()
+    };
+    assert_eq!(
+        local_time(&ParseOptions::default()),
+        DataType::Datetime(TimeUnit::Milliseconds, Some("UTC".into()))
+    );
+
+    let options = ParseOptions {
+        timezone: "Europe/Stockholm".parse().unwrap(),
+        ..ParseOptions::default()
+    };
+    assert_eq!(
+        local_time(&options),
+        DataType::Datetime(TimeUnit::Milliseconds, Some("Europe/Stockholm".into()))
+    );
+
+    let result = "Europe/Atlantis".parse::<Timezone>();
+    assert!(matches!(result, Err(ParseError::UnknownTimezone { .. })));
+}
+
 #[test]
 fn parse_art_dir() {
-    let test = article::parse_art_folder("testdata/art/").unwrap();
+    let test = article::parse_art_folder("testdata/art/", &ParseOptions::default()).unwrap();
     assert_eq!(test.len(), 5);
+    assert!(test.contains_key(&KsmFileKey::new(202, Some(1))));
+}
+
+/// Temporary folder for a test, removed when dropped so that failing tests clean up as well.
+struct TemporaryDir(PathBuf);
+
+impl TemporaryDir {
+    fn new(test_name: &str) -> Self {
+        let path =
+            std::env::temp_dir().join(format!("ksmparser_{}_{}", test_name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TemporaryDir(path)
+    }
+}
+
+impl Deref for TemporaryDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TemporaryDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TemporaryDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+#[test]
+fn parse_dat_dir_with_invalid_files() {
+    let dir = TemporaryDir::new("folder");
+    fs::copy("testdata/valid.dat", dir.join("00100.dat")).unwrap();
+    fs::copy("testdata/uneven_row.dat", dir.join("00200.dat")).unwrap();
+    fs::copy("testdata/uneven_col.dat", dir.join("00300-1.dat")).unwrap();
+
+    let result = measurement::parse_dat_folder_parallel(&dir, &ParseOptions::default()).unwrap();
+    assert_eq!(result.parsed.len(), 1);
+    assert!(result.parsed.contains_key(&KsmFileKey::new(100, None)));
+    assert_eq!(result.failed.len(), 2);
+    let error = &result.failed["00300-1.dat"];
+    assert_eq!(
+        error.location().unwrap().path,
+        Some(dir.join("00300-1.dat"))
+    );
+
+    // The serial variant reports the first failed file
+    let error = measurement::parse_dat_folder(&dir, &ParseOptions::default()).unwrap_err();
+    assert_eq!(error.location().unwrap().path, Some(dir.join("00200.dat")));
+}
+
+/// Writes a gzip compressed copy of `source` to `target`.
+fn write_gzip(source: &str, target: &PathBuf) {
+    let mut encoder = GzEncoder::new(fs::File::create(target).unwrap(), Compression::default());
+    encoder.write_all(&fs::read(source).unwrap()).unwrap();
+    encoder.finish().unwrap();
+}
+
+#[test]
+fn parse_compressed_and_archived_files() {
+    let dir = TemporaryDir::new("archive");
+    let options = ParseOptions::default();
+    let expected = measurement::parse_dat_file("testdata/valid.dat", &options).unwrap();
+
+    // Gzip compressed files are decompressed transparently
+    write_gzip("testdata/valid.dat", &dir.join("00100.dat.gz"));
+    let parsed = measurement::parse_dat_file(dir.join("00100.dat.gz"), &options).unwrap();
+    assert!(parsed.equals_missing(&expected));
+    let (update, _, _) =
+        measurement::parse_dat_file_from(dir.join("00100.dat.gz"), &DatCursor::default(), &options)
+            .unwrap();
+    assert!(matches!(update, DatUpdate::Full(_)));
+    write_gzip("testdata/valid.art", &dir.join("00100.art.gz"));
+    let parsed = article::parse_art_file(dir.join("00100.art.gz"), &options).unwrap();
+    assert_eq!(
+        parsed.column("pgm_name").unwrap().str().unwrap().get(0),
+        Some("round_local")
+    );
+
+    // Entries of zip archives are listed and parsed like files in a folder
+    let mut writer = ZipWriter::new(fs::File::create(dir.join("2024-01.zip")).unwrap());
+    let entries = [
+        ("00200.dat", "testdata/valid.dat"),
+        ("old/00300-1.dat", "testdata/valid.dat"),
+        ("00400.dat", "testdata/uneven_row.dat"),
+        ("readme.txt", "testdata/art/readme.txt"),
+    ];
+    for (name, source) in entries {
+        writer
+            .start_file(name, SimpleFileOptions::default())
+            .unwrap();
+        writer.write_all(&fs::read(source).unwrap()).unwrap();
+    }
+    writer.finish().unwrap();
+
+    let discovery = Discovery::new("dat").unwrap();
+    let listed = archive::list_archive(dir.join("2024-01.zip"), &discovery).unwrap();
+    let names: Vec<&str> = listed.iter().map(|entry| entry.name.as_str()).collect();
+    assert_eq!(names, ["00200.dat", "00400.dat", "old/00300-1.dat"]);
+    assert_eq!(listed[2].key, KsmFileKey::new(300, Some(1)));
+
+    let result = archive::parse_dat_archive(dir.join("2024-01.zip"), &options).unwrap();
+    assert_eq!(result.parsed.len(), 2);
+    assert!(result.parsed[&KsmFileKey::new(200, None)].equals_missing(&expected));
+    assert_eq!(
+        result.failed["00400.dat"].location().unwrap().path,
+        Some(dir.join("2024-01.zip").join("00400.dat"))
+    );
+
+    // Discovery includes compressed files and archive entries only when asked to
+    assert!(discovery.discover(&dir).unwrap().files.is_empty());
+    let discovery = discovery.compressed(true).archives(true);
+    let files = discovery.discover(&dir).unwrap().files;
+    let paths: Vec<PathBuf> = files.iter().map(|file| file.full_path()).collect();
+    assert_eq!(
+        paths,
+        [
+            dir.join("00100.dat.gz"),
+            dir.join("2024-01.zip/00200.dat"),
+            dir.join("2024-01.zip/00400.dat"),
+            dir.join("2024-01.zip/old/00300-1.dat"),
+        ]
+    );
+    assert!(files.iter().all(|file| file.is_archived()));
+    for file in &files[..2] {
+        let parsed = measurement::parse_dat_reader(file.open().unwrap(), &options).unwrap();
+        assert!(parsed.equals_missing(&expected));
+    }
+    // A damaged archive is reported without hiding the other files
+    fs::write(dir.join("2024-02.zip"), b"PK\x03\x04 truncated").unwrap();
+    let discovered = discovery.discover(&dir).unwrap();
+    assert_eq!(discovered.files.len(), 4);
+    assert_eq!(discovered.failed.len(), 1);
+    assert_eq!(discovered.failed[0].path, dir.join("2024-02.zip"));
+    assert!(matches!(
+        discovered.failed[0].error,
+        ParseError::ArchiveError { .. }
+    ));
+}
+
+#[test]
+fn discover_ksm_files() {
+    let discovery = Discovery::new("dat").unwrap();
+    assert_eq!(
+        discovery.key("00202-1.dat"),
+        Some(KsmFileKey::new(202, Some(1)))
+    );
+    assert_eq!(discovery.key("00202-1.art"), None);
+    assert_eq!(discovery.key("20.dat"), None);
+    assert_eq!(discovery.key("00202-12.dat"), None);
+
+    let files = Discovery::new("art")
+        .unwrap()
+        .discover("testdata/art")
+        .unwrap()
+        .files;
+    let names: Vec<&str> = files.iter().map(|file| file.file_name.as_str()).collect();
+    assert_eq!(
+        names,
+        [
+            "00202-1.art",
+            "00202.art",
+            "00310.art",
+            "01204.art",
+            "12345.art"
+        ]
+    );
+    assert!(files.iter().all(|file| file.len > 0));
+
+    // Custom patterns take the key from the capture groups
+    let files = Discovery::with_pattern(r"^(?P<article>\d+)\.art$")
+        .unwrap()
+        .discover("testdata/art")
+        .unwrap()
+        .files;
+    assert_eq!(files.len(), 4);
+    assert!(matches!(
+        Discovery::with_pattern("(unclosed"),
+        Err(ParseError::InvalidRegex { .. })
+    ));
+
+    // Subfolders are only searched when asked to
+    let discovery = Discovery::new("art").unwrap();
+    assert!(discovery.discover("testdata").unwrap().files.is_empty());
+    let files = discovery
+        .recursive(true)
+        .discover("testdata")
+        .unwrap()
+        .files;
+    assert_eq!(files.len(), 5);
+    assert_eq!(files[0].path, PathBuf::from("testdata/art/00202-1.art"));
+}
+
+#[test]
+fn discover_colliding_file_keys() {
+    let dir = TemporaryDir::new("colliding_keys");
+    for name in ["202.dat", "0202.dat", "00202.dat", "00300.dat"] {
+        fs::copy("testdata/valid.dat", dir.join(name)).unwrap();
+    }
+
+    let discovered = Discovery::new("dat").unwrap().discover(&dir).unwrap();
+    let names: Vec<&str> = discovered
+        .files
+        .iter()
+        .map(|file| file.file_name.as_str())
+        .collect();
+    assert_eq!(names, ["00300.dat"]);
+    assert_eq!(discovered.failed.len(), 3);
+    match &discovered.failed[0].error {
+        ParseError::DuplicateFileKey { key, others, .. } => {
+            assert_eq!(*key, KsmFileKey::new(202, None));
+            assert_eq!(others.len(), 2);
+        }
+        error => panic!("Expected a duplicate file key, got {:?}", error),
+    }
+
+    // None of the colliding files is parsed in place of the others
+    let result = measurement::parse_dat_folder_parallel(&dir, &ParseOptions::default()).unwrap();
+    assert_eq!(result.parsed.len(), 1);
+    assert!(result.parsed.contains_key(&KsmFileKey::new(300, None)));
+    for name in ["202.dat", "0202.dat", "00202.dat"] {
+        assert!(matches!(
+            result.failed[name],
+            ParseError::DuplicateFileKey { .. }
+        ));
+    }
+}
+
+#[test]
+fn discover_recursive_duplicate_file_keys() {
+    let dir = TemporaryDir::new("recursive_duplicates");
+    for folder in ["sub1", "sub2"] {
+        fs::create_dir_all(dir.join(folder)).unwrap();
+        fs::copy("testdata/valid.dat", dir.join(folder).join("00202.dat")).unwrap();
+    }
+    fs::copy("testdata/valid.dat", dir.join("sub1").join("00300.dat")).unwrap();
+
+    let discovered = Discovery::new("dat")
+        .unwrap()
+        .recursive(true)
+        .discover(&dir)
+        .unwrap();
+    assert_eq!(discovered.files.len(), 1);
+    assert_eq!(discovered.files[0].key, KsmFileKey::new(300, None));
+    let failed: Vec<&PathBuf> = discovered
+        .failed
+        .iter()
+        .map(|failure| &failure.path)
+        .collect();
+    assert_eq!(
+        failed,
+        [
+            &dir.join("sub1").join("00202.dat"),
+            &dir.join("sub2").join("00202.dat")
+        ]
+    );
+    match &discovered.failed[0].error {
+        ParseError::DuplicateFileKey { others, .. } => {
+            assert_eq!(*others, [dir.join("sub2").join("00202.dat")]);
+        }
+        error => panic!("Expected a duplicate file key, got {:?}", error),
+    }
+
+    // History may share the key of a current file
+    write_gzip("testdata/valid.dat", &dir.join("00300.dat.gz"));
+    let discovered = Discovery::new("dat")
+        .unwrap()
+        .recursive(true)
+        .compressed(true)
+        .discover(&dir)
+        .unwrap();
+    assert_eq!(discovered.files.len(), 2);
+    assert_eq!(discovered.failed.len(), 2);
+}
+
+#[test]
+fn parse_file_keys() {
+    let key = KsmFileKey::new(202, Some(1));
+    assert_eq!("00202-1.dat".parse::<KsmFileKey>().unwrap(), key);
+    assert_eq!("202-1".parse::<KsmFileKey>().unwrap(), key);
+    assert_eq!(
+        "202".parse::<KsmFileKey>().unwrap(),
+        KsmFileKey::new(202, None)
+    );
+    assert_eq!(key.to_string(), "00202-1");
+    assert_eq!(key.file_name("art"), "00202-1.art");
+    assert!(matches!(
+        "readme.txt".parse::<KsmFileKey>(),
+        Err(ParseError::InvalidFileKey { .. })
+    ));
+
+    // Articles order numerically with the base article before its variants
+    let mut keys: Vec<KsmFileKey> = ["1204", "00202-1", "12345", "202", "00310"]
+        .iter()
+        .map(|name| name.parse().unwrap())
+        .collect();
+    keys.sort();
+    let names: Vec<String> = keys.iter().map(|key| key.to_string()).collect();
+    assert_eq!(names, ["00202", "00202-1", "00310", "01204", "12345"]);
+}
+
+/// Copies `testdata/valid.dat` to a temporary file unique to the calling test.
+fn temporary_dat_copy(test_name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!(
+        "ksmparser_{}_{}.dat",
+        test_name,
+        std::process::id()
+    ));
+    fs::copy("testdata/valid.dat", &path).unwrap();
+    path
+}
+
+#[test]
+fn parse_appended_measurement_data() {
+    let path = temporary_dat_copy("appended");
+
+    let (update, cursor, _) =
+        measurement::parse_dat_file_from(&path, &DatCursor::default(), &ParseOptions::default())
+            .unwrap();
+    let dataframe = match update {
+        DatUpdate::Full(df) => df,
+        DatUpdate::Appended(_) => panic!("First parse should be a full parse"),
+    };
+    assert_eq!(dataframe.height(), 4);
+    assert_eq!(cursor.offset(), fs::metadata(&path).unwrap().len());
+
+    // Append one complete entry and one entry that is still being written
+    let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+    file.write_all(b"\"measure_time1970\"\t\"wall_min\"\t\"ovality\"\n1700000240\t0.18\t0.4\n")
+        .unwrap();
+    file.write_all(b"\"measure_time1970\"\t\"wall_min\"\n17000003")
+        .unwrap();
+
+    let (update, cursor, _) =
+        measurement::parse_dat_file_from(&path, &cursor, &ParseOptions::default()).unwrap();
+    let new_entries = match update {
+        DatUpdate::Appended(df) => df,
+        DatUpdate::Full(_) => panic!("Growing file should not be fully parsed"),
+    };
+    assert_eq!(new_entries.height(), 1);
+
+    let combined = measurement::append_entries(&dataframe, new_entries).unwrap();
+    assert_eq!(combined.height(), 5);
+    assert_eq!(combined.width(), 5, "Late column should be added");
+    assert_eq!(combined.column("ovality").unwrap().null_count(), 4);
+
+    // Finish the pending entry
+    file.write_all(b"00\t0.17\n").unwrap();
+    let (update, _, _) =
+        measurement::parse_dat_file_from(&path, &cursor, &ParseOptions::default()).unwrap();
+    match update {
+        DatUpdate::Appended(df) => assert_eq!(df.height(), 1),
+        DatUpdate::Full(_) => panic!("Growing file should not be fully parsed"),
+    }
+
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn parse_finished_measurement_data_at_end_of_file() {
+    let path = temporary_dat_copy("finished");
+    let options = ParseOptions {
+        lenient: true,
+        ..ParseOptions::default()
+    };
+
+    // The final value line has no trailing newline
+    let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+    file.write_all(b"\"measure_time1970\"\t\"wall_min\"\n1700000240\t0.18")
+        .unwrap();
+    let (update, cursor, _) =
+        measurement::parse_dat_file_from(&path, &DatCursor::default(), &options).unwrap();
+    match update {
+        DatUpdate::Full(df) => assert_eq!(df.height(), 4),
+        DatUpdate::Appended(_) => panic!("First parse should be a full parse"),
+    }
+    assert!(cursor.has_pending());
+
+    // The file did not grow, so the end of the file ends the entry
+    let (update, cursor, _) = measurement::parse_dat_file_from(&path, &cursor, &options).unwrap();
+    match update {
+        DatUpdate::Appended(df) => assert_eq!(df.height(), 1),
+        DatUpdate::Full(_) => panic!("Unchanged file should not be fully parsed"),
+    }
+    assert!(
+        cursor.has_pending(),
+        "The unterminated line may still be finished"
+    );
+    assert_eq!(cursor.pending_entries(), 1);
+
+    // A column line without values at the end is reported once the file stops growing
+    file.write_all(b"\n\"measure_time1970\"\t\"wall_min\"\n")
+        .unwrap();
+    let (_, cursor, report) = measurement::parse_dat_file_from(&path, &cursor, &options).unwrap();
+    assert!(report.issues.is_empty());
+    let (update, cursor, report) =
+        measurement::parse_dat_file_from(&path, &cursor, &options).unwrap();
+    match update {
+        DatUpdate::Appended(df) => assert_eq!(df.height(), 0),
+        DatUpdate::Full(_) => panic!("Unchanged file should not be fully parsed"),
+    }
+    assert_eq!(report.issues.len(), 1);
+    assert_eq!(report.issues[0].action, IssueAction::Skipped);
+    assert_eq!(report.issues[0].location.line, Some(12));
+    assert_eq!(cursor.pending_entries(), 0);
+
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn parse_finished_line_after_end_of_file() {
+    let path = temporary_dat_copy("finished_line");
+    let options = ParseOptions::default();
+
+    // The writer pauses in the middle of the final value line
+    let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+    file.write_all(b"\"measure_time1970\"\t\"wall_min\"\n1700000240\t0.1")
+        .unwrap();
+    let (update, cursor, _) =
+        measurement::parse_dat_file_from(&path, &DatCursor::default(), &options).unwrap();
+    let dataframe = match update {
+        DatUpdate::Full(df) => df,
+        DatUpdate::Appended(_) => panic!("First parse should be a full parse"),
+    };
+    let offset = cursor.offset();
+
+    // The file stopped growing, so the half-written line is parsed, but the cursor stays before it
+    let (update, cursor, _) = measurement::parse_dat_file_from(&path, &cursor, &options).unwrap();
+    let pending = match update {
+        DatUpdate::Appended(df) => df,
+        DatUpdate::Full(_) => panic!("Unchanged file should not be fully parsed"),
+    };
+    assert_eq!(pending.height(), 1);
+    assert_eq!(
+        pending.column("wall_min").unwrap().f64().unwrap().get(0),
+        Some(0.1)
+    );
+    assert_eq!(cursor.offset(), offset);
+    assert_eq!(cursor.pending_entries(), 1);
+
+    // The writer finishes the line, which is parsed from its start again
+    file.write_all(b"8\n").unwrap();
+    let (update, cursor, report) =
+        measurement::parse_dat_file_from(&path, &cursor, &options).unwrap();
+    let new_entries = match update {
+        DatUpdate::Appended(df) => df,
+        DatUpdate::Full(_) => panic!("Growing file should not be fully parsed"),
+    };
+    assert!(report.issues.is_empty());
+    assert_eq!(new_entries.height(), 1);
+    assert_eq!(
+        new_entries
+            .column("wall_min")
+            .unwrap()
+            .f64()
+            .unwrap()
+            .get(0),
+        Some(0.18)
+    );
+    assert_eq!(cursor.offset(), fs::metadata(&path).unwrap().len());
+    assert_eq!(cursor.pending_entries(), 0);
+
+    let combined = measurement::append_entries(&dataframe, new_entries).unwrap();
+    assert_eq!(combined.height(), 5);
+
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn append_entries_with_differing_types() {
+    let dataframe = df!(
+        "measure_time1970" => [1700000000i64],
+        "wall_min" => [1i64],
+        "info4" => [3i32],
+        "cable_parts" => [6i32]
+    )
+    .unwrap();
+    let new_entries = df!(
+        "measure_time1970" => [1700000060i64],
+        "wall_min" => [0.22f64],
+        "info4" => ["L3"],
+        "cable_parts" => [7i64]
+    )
+    .unwrap();
+
+    let (combined, report) =
+        measurement::append_entries_with_report(&dataframe, new_entries).unwrap();
+    assert_eq!(combined.height(), 2);
+    assert_eq!(
+        combined.column("wall_min").unwrap().dtype(),
+        &DataType::Float64
+    );
+    assert_eq!(combined.column("info4").unwrap().dtype(), &DataType::String);
+    assert_eq!(
+        combined.column("cable_parts").unwrap().dtype(),
+        &DataType::Int64
+    );
+    assert_eq!(
+        combined.column("info4").unwrap().str().unwrap().get(0),
+        Some("3")
+    );
+
+    // Every converted column is reported
+    let reasons: Vec<&str> = report.issues.iter().map(|i| i.reason.as_str()).collect();
+    assert_eq!(
+        reasons,
+        [
+            "Converted wall_min from i64 to f64",
+            "Converted info4 from i32 to str",
+            "Converted cable_parts from i32 to i64"
+        ]
+    );
+    assert!(report
+        .issues
+        .iter()
+        .all(|issue| issue.action == IssueAction::Promoted));
+}
+
+#[test]
+fn parse_truncated_measurement_data() {
+    let path = temporary_dat_copy("truncated");
+    let (_, cursor, _) =
+        measurement::parse_dat_file_from(&path, &DatCursor::default(), &ParseOptions::default())
+            .unwrap();
+
+    // Rewrite the file with fewer entries than before
+    fs::write(
+        &path,
+        "\"measure_time1970\"\t\"wall_min\"\n1700000000\t0.3\n",
+    )
+    .unwrap();
+
+    let (update, _, _) =
+        measurement::parse_dat_file_from(&path, &cursor, &ParseOptions::default()).unwrap();
+    match update {
+        DatUpdate::Full(df) => assert_eq!(df.height(), 1),
+        DatUpdate::Appended(_) => panic!("Truncated file should be fully parsed"),
+    }
+
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn parse_with_configured_encoding() {
+    let utf8 = "round_local\nNone\ninfo5 = Åsa Öberg\n".as_bytes();
+    let windows_1252 = b"round_local\nNone\ninfo5 = 100\x80\n";
+
+    let operator = |df: DataFrame| {
+        df.column("info5")
+            .unwrap()
+            .str()
+            .unwrap()
+            .get(0)
+            .unwrap()
+            .to_string()
+    };
+
+    // The default ISO_8859_10 decoding garbles UTF-8 content
+    let result = article::parse_art_bytes(utf8, &ParseOptions::default()).unwrap();
+    assert_ne!(operator(result), "Åsa Öberg");
+
+    let auto = ParseOptions {
+        encoding: TextEncoding::Auto,
+        ..ParseOptions::default()
+    };
+    let result = article::parse_art_bytes(utf8, &auto).unwrap();
+    assert_eq!(operator(result), "Åsa Öberg");
+
+    let cp1252 = ParseOptions {
+        encoding: "windows-1252".parse().unwrap(),
+        ..ParseOptions::default()
+    };
+    let result = article::parse_art_bytes(windows_1252, &cp1252).unwrap();
+    assert_eq!(operator(result), "100€");
+
+    // Invalid UTF-8 is decoded with the ISO_8859_10 fallback in auto mode
+    let result = article::parse_art_bytes(b"round_local\nNone\ninfo5 = \xc5sa\n", &auto).unwrap();
+    assert_eq!(operator(result), "Åsa");
+
+    assert!(matches!(
+        "klingon".parse::<TextEncoding>(),
+        Err(ParseError::UnknownEncoding { .. })
+    ));
 }
@@ -0,0 +1,146 @@
+use ksmparser::{article, measurement, ParseError, ParseOptions};
+use polars::prelude::*;
+use proptest::prelude::*;
+
+/// Characters outside ASCII that ISO-8859-10 can represent
+const NORDIC: &str = "ÅÄÖåäöÆæØøÐðÞþŊŋŦŧ";
+
+/// Writes article parameters and parses them back.
+fn art_round_trip(dataframe: &DataFrame) -> DataFrame {
+    let mut bytes = Vec::new();
+    article::write_art(&mut bytes, dataframe).unwrap();
+    article::parse_art_bytes(&bytes, &ParseOptions::default()).unwrap()
+}
+
+/// Writes measurement entries and parses them back with the columns in the original order.
+fn dat_round_trip(dataframe: &DataFrame) -> DataFrame {
+    let mut bytes = Vec::new();
+    measurement::write_dat(&mut bytes, dataframe).unwrap();
+    let parsed = measurement::parse_dat_bytes(&bytes, &ParseOptions::default()).unwrap();
+    parsed.select(dataframe.get_column_names_owned()).unwrap()
+}
+
+#[test]
+fn write_parsed_article() {
+    let dataframe =
+        article::parse_art_file("testdata/valid.art", &ParseOptions::default()).unwrap();
+    assert!(art_round_trip(&dataframe).equals_missing(&dataframe));
+
+    // The output is ISO-8859-10 encoded
+    let dataframe = df!("pgm_name" => ["round_local"], "info5" => ["Åsa"]).unwrap();
+    let mut bytes = Vec::new();
+    article::write_art(&mut bytes, &dataframe).unwrap();
+    assert_eq!(bytes, b"round_local\nNone\ninfo5 = \xc5sa\n");
+}
+
+#[test]
+fn write_parsed_measurement_data() {
+    let dataframe = measurement::parse_dat_file("testdata/valid.dat", &ParseOptions::default())
+        .unwrap()
+        .drop("local_time")
+        .unwrap();
+    assert!(dat_round_trip(&dataframe).equals_missing(&dataframe));
+}
+
+#[test]
+fn write_unrepresentable_values() {
+    let dataframe = df!("pgm_name" => ["round_local"], "info5" => ["100€"]).unwrap();
+    let error = article::write_art(Vec::new(), &dataframe).unwrap_err();
+    assert!(matches!(error, ParseError::UnwritableValue { .. }));
+    assert_eq!(error.location().unwrap().line, Some(3));
+
+    let dataframe = df!("info5" => ["Anna"]).unwrap();
+    let result = article::write_art(Vec::new(), &dataframe);
+    assert!(matches!(result, Err(ParseError::MissingField { .. })));
+
+    let dataframe = df!(
+        "measure_time1970" => [1700000000i64, 1700000060],
+        "info5" => ["Anna", "Anna\tLisa"]
+    )
+    .unwrap();
+    let error = measurement::write_dat(Vec::new(), &dataframe).unwrap_err();
+    assert!(matches!(error, ParseError::UnwritableValue { .. }));
+    assert_eq!(error.location().unwrap().line, Some(4));
+}
+
+/// A string of ASCII and Nordic letters, digits and punctuation without surrounding whitespace.
+fn text() -> impl Strategy<Value = String> {
+    let pattern = format!(
+        "([a-zA-Z0-9,.{0}-]([a-zA-Z0-9,. {0}-]{{0,10}}[a-zA-Z0-9,.{0}-])?)?",
+        NORDIC
+    );
+    proptest::string::string_regex(&pattern).unwrap()
+}
+
+fn float() -> impl Strategy<Value = f64> {
+    prop::num::f64::NORMAL | prop::num::f64::SUBNORMAL | prop::num::f64::ZERO
+}
+
+proptest! {
+    #[test]
+    fn article_round_trip(
+        pgm_name in "[a-z][a-z_]{0,12}",
+        parameters in prop::collection::btree_map("info[0-9]{1,2}|text_[a-z_]{1,20}", text(), 0..12),
+        limits in prop::collection::btree_map(
+            "check_(wall_min|wall_mean|diameter_outer_mean|user2)_(min|nom|max)limit|centervalue|area_(outer|wall)",
+            prop::option::of(float()),
+            0..8,
+        ),
+    ) {
+        let mut columns = vec![Column::new("pgm_name".into(), [pgm_name])];
+        for (key, value) in parameters {
+            columns.push(Column::new(key.into(), [value]));
+        }
+        // Numeric keys are parsed back as Float64
+        for (key, value) in limits {
+            columns.push(Column::new(key.into(), [value]));
+        }
+        let dataframe = DataFrame::new(columns).unwrap();
+
+        prop_assert!(art_round_trip(&dataframe).equals_missing(&dataframe));
+    }
+
+    #[test]
+    fn measurement_round_trip(
+        entries in prop::collection::vec(
+            (
+                0i64..4_000_000_000,
+                prop::option::of(float()),
+                prop::option::of(float()),
+                prop::option::of(text()),
+            ),
+            1..20,
+        ),
+    ) {
+        let mut times = Vec::new();
+        let mut wall_min = Vec::new();
+        let mut ovality = Vec::new();
+        let mut info5 = Vec::new();
+        for (time, wall, oval, info) in entries {
+            times.push(time);
+            wall_min.push(wall);
+            ovality.push(oval);
+            info5.push(info);
+        }
+        let mut dataframe = df!(
+            "measure_time1970" => times,
+            "wall_min" => wall_min,
+            "ovality" => ovality,
+            "info5" => info5
+        )
+        .unwrap();
+
+        // Columns without any values cannot be represented in the file
+        let empty: Vec<PlSmallStr> = dataframe
+            .get_columns()
+            .iter()
+            .filter(|column| column.null_count() == column.len())
+            .map(|column| column.name().clone())
+            .collect();
+        for name in empty {
+            dataframe = dataframe.drop(&name).unwrap();
+        }
+
+        prop_assert!(dat_round_trip(&dataframe).equals_missing(&dataframe));
+    }
+}
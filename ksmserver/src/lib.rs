@@ -1,10 +1,22 @@
+pub mod output;
+
+use chrono::{DateTime, Utc};
 use dashmap::DashMap;
-use ksmparser::ParseError;
+use ksmparser::discovery::{DiscoveredFile, Discovery};
+use ksmparser::key::KsmFileKey;
+use ksmparser::measurement::{append_entries, append_entries_with_report, DatCursor, DatUpdate};
+use ksmparser::schema::ColumnSchema;
+use ksmparser::{
+    DuplicatePolicy, Location, ParseError, ParseOptions, ParseReport, TextEncoding, Timezone,
+};
+use output::CsvOptions;
 use polars::prelude::*;
-use regex::Regex;
+use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
 use std::env;
 use std::fmt;
-use std::fs;
+use std::io::Read;
 use std::path::PathBuf;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::SystemTime;
@@ -15,12 +27,88 @@ pub struct Environment {
     pub bind_addr: String,
     pub art_path: String,
     pub dat_path: String,
+    pub art_discovery: Discovery,
+    pub dat_discovery: Discovery,
+    pub parse_options: ParseOptions,
+    pub csv: CsvOptions,
 }
 
 impl Environment {
     // Read environment variables
-    pub fn new() -> Environment {
-        Environment {
+    pub fn new() -> Result<Environment, KSMError> {
+        // Encoding of the KSM files. Accepts "auto" or an encoding label such as "utf-8"
+        let encoding = match env::var("KSM_ENCODING") {
+            Ok(label) => {
+                label
+                    .parse::<TextEncoding>()
+                    .map_err(|e| KSMError::ConfigurationError {
+                        variable: "KSM_ENCODING".to_string(),
+                        reason: e.to_string(),
+                    })?
+            }
+            Err(_) => TextEncoding::default(),
+        };
+
+        // Skip malformed measurement entries instead of rejecting the whole file, off by default
+        let lenient = env_bool("KSM_LENIENT", false)?;
+
+        // Handling of repeated column names in a measurement entry: reject, first, last or rename
+        let duplicates = match env::var("KSM_DUPLICATES") {
+            Ok(name) => {
+                name.parse::<DuplicatePolicy>()
+                    .map_err(|e| KSMError::ConfigurationError {
+                        variable: "KSM_DUPLICATES".to_string(),
+                        reason: e.to_string(),
+                    })?
+            }
+            Err(_) => DuplicatePolicy::default(),
+        };
+
+        // Optional TOML or JSON file with additional measurement columns
+        let schema = match env::var("KSM_SCHEMA") {
+            Ok(path) => {
+                ColumnSchema::from_file(&path).map_err(|e| KSMError::ConfigurationError {
+                    variable: "KSM_SCHEMA".to_string(),
+                    reason: e.to_string(),
+                })?
+            }
+            Err(_) => ColumnSchema::default(),
+        };
+
+        // Time zone of the local_time column, checked here so that a typo fails at startup
+        let timezone = env::var("TIMEZONE")
+            .unwrap_or(String::from("Europe/Stockholm"))
+            .parse::<Timezone>()
+            .map_err(|e| KSMError::ConfigurationError {
+                variable: "TIMEZONE".to_string(),
+                reason: e.to_string(),
+            })?;
+
+        // Search subfolders of the KSM paths as well
+        let recursive = env_bool("KSM_RECURSIVE", false)?;
+
+        // Load rotated measurement history from .gz files and .zip archives
+        let history = env_bool("KSM_HISTORY", true)?;
+
+        // Default delimiter of CSV responses, a single character or "tab"
+        let delimiter = match env::var("KSM_CSV_DELIMITER") {
+            Ok(value) => {
+                output::parse_delimiter(&value).map_err(|reason| KSMError::ConfigurationError {
+                    variable: "KSM_CSV_DELIMITER".to_string(),
+                    reason,
+                })?
+            }
+            Err(_) => CsvOptions::default().delimiter,
+        };
+
+        // Write floats in CSV responses with a decimal comma by default
+        let decimal_comma = env_bool("KSM_CSV_DECIMAL_COMMA", false)?;
+
+        Ok(Environment {
+            art_discovery: discovery_from_env("KSM_ART_PATTERN", "art", recursive)?,
+            dat_discovery: discovery_from_env("KSM_DAT_PATTERN", "dat", recursive)?
+                .compressed(history)
+                .archives(history),
             bind_addr: env::var("BIND_ADDRESS").unwrap_or(String::from("127.0.0.1:8080")),
             art_path: env::var("KSM_ART_PATH")
                 .unwrap_or(String::from("."))
@@ -28,12 +116,56 @@ impl Environment {
             dat_path: env::var("KSM_DAT_PATH")
                 .unwrap_or(String::from("."))
                 .to_owned(),
-        }
+            parse_options: ParseOptions {
+                encoding,
+                lenient,
+                schema: Arc::new(schema),
+                timezone,
+                duplicates,
+            },
+            csv: CsvOptions {
+                delimiter,
+                decimal_comma,
+            },
+        })
     }
 }
 
+/// Reads the boolean in `variable`, or `default` if it is not set.
+fn env_bool(variable: &str, default: bool) -> Result<bool, KSMError> {
+    match env::var(variable) {
+        Ok(value) => value
+            .parse::<bool>()
+            .map_err(|e| KSMError::ConfigurationError {
+                variable: variable.to_string(),
+                reason: e.to_string(),
+            }),
+        Err(_) => Ok(default),
+    }
+}
+
+/// Creates a file discovery using the file name pattern in `variable`, or the default pattern
+/// for `file_extension` if it is not set.
+fn discovery_from_env(
+    variable: &str,
+    file_extension: &str,
+    recursive: bool,
+) -> Result<Discovery, KSMError> {
+    let discovery = match env::var(variable) {
+        Ok(pattern) => Discovery::with_pattern(&pattern),
+        Err(_) => Discovery::new(file_extension),
+    };
+    discovery
+        .map(|discovery| discovery.recursive(recursive))
+        .map_err(|e| KSMError::ConfigurationError {
+            variable: variable.to_string(),
+            reason: e.to_string(),
+        })
+}
+
 pub enum KSMError {
     DateCreationError { date: String, reason: String },
+    ConfigurationError { variable: String, reason: String },
 }
 
 impl fmt::Display for KSMError {
@@ -45,94 +177,543 @@ impl fmt::Display for KSMError {
             } => {
                 write!(f, "Error creating date '{}': {}", date, reason)
             }
+            KSMError::ConfigurationError {
+                ref variable,
+                ref reason,
+            } => {
+                write!(f, "Invalid configuration in {}: {}", variable, reason)
+            }
         }
     }
 }
 
 /// Represents the state of the server application, holding shared resources.
 #[derive(Clone)]
-pub struct AppState<'a> {
-    pub measurement_data: Arc<KSMData<'a>>,
-    pub parameter_data: Arc<KSMData<'a>>,
+pub struct AppState {
+    pub measurement_data: Arc<KSMData>,
+    pub parameter_data: Arc<KSMData>,
+    /// Default settings of CSV responses
+    pub csv: CsvOptions,
 }
 
 /// Represents a structure for storing the contents of a KSMFile and its modification time
 pub struct KSMFile {
     pub dataframe: DataFrame,
+    /// Path the data was loaded from, so that a file replacing it under the same key is loaded from the start
+    path: PathBuf,
     modified: SystemTime,
+    cursor: Option<DatCursor>,
+}
+
+/// Summary of the data loaded for a key, as listed by the `/measurement` and `/parameters`
+/// endpoints.
+#[derive(Debug, Clone, Serialize)]
+pub struct KSMFileSummary {
+    pub key: KsmFileKey,
+    /// Article number parsed from the file name
+    pub article: u32,
+    pub variant: Option<u8>,
+    /// Latest modification of the current file or its history
+    pub modified: DateTime<Utc>,
+    pub rows: usize,
+    /// Column names in order of first appearance
+    pub columns: Vec<String>,
+    /// Earliest `measure_time1970`, if the data has measurement times
+    pub first_measure_time: Option<i64>,
+    /// Latest `measure_time1970`, if the data has measurement times
+    pub last_measure_time: Option<i64>,
+}
+
+impl KSMFileSummary {
+    /// Creates an empty summary of a key.
+    fn new(key: KsmFileKey) -> Self {
+        KSMFileSummary {
+            key,
+            article: key.article,
+            variant: key.variant,
+            modified: DateTime::UNIX_EPOCH,
+            rows: 0,
+            columns: Vec::new(),
+            first_measure_time: None,
+            last_measure_time: None,
+        }
+    }
+
+    /// Adds the data of a current or history file to the summary.
+    fn add(&mut self, ksmfile: &KSMFile) {
+        self.modified = self.modified.max(ksmfile.modified.into());
+        self.rows += ksmfile.dataframe.height();
+        for name in ksmfile.dataframe.get_column_names() {
+            if !self.columns.iter().any(|column| column == name.as_str()) {
+                self.columns.push(name.to_string());
+            }
+        }
+
+        let measure_time = ksmfile
+            .dataframe
+            .column("measure_time1970")
+            .and_then(|column| column.cast(&DataType::Int64));
+        if let Ok(measure_time) = measure_time {
+            if let Ok(measure_time) = measure_time.i64() {
+                let (first, last) = (measure_time.min(), measure_time.max());
+                self.first_measure_time = match (self.first_measure_time, first) {
+                    (Some(current), Some(first)) => Some(current.min(first)),
+                    (current, first) => current.or(first),
+                };
+                self.last_measure_time = self.last_measure_time.max(last);
+            }
+        }
+    }
+}
+
+/// Function parsing a whole KSM file
+pub type ParseFunction =
+    fn(file_path: PathBuf, options: &ParseOptions) -> Result<DataFrame, ParseError>;
+
+/// Function parsing a KSM file from a reader, used for compressed and archived files
+pub type ReaderParseFunction =
+    fn(reader: Box<dyn Read + Send>, options: &ParseOptions) -> Result<DataFrame, ParseError>;
+
+/// Function parsing the entries appended to a KSM file after a cursor
+pub type IncrementalParseFunction = fn(
+    file_path: PathBuf,
+    cursor: &DatCursor,
+    options: &ParseOptions,
+) -> Result<(DatUpdate, DatCursor, ParseReport), ParseError>;
+
+/// Describes how a file is loaded when it has been modified.
+#[derive(Clone, Copy)]
+pub enum Loader {
+    /// The whole file is parsed on every modification
+    Full(ParseFunction),
+    /// Only entries appended since the previous load are parsed
+    Incremental(IncrementalParseFunction),
 }
+
 /// Represents a structure that holds and manages data frames loaded from files in the KSM system.
-pub struct KSMData<'a> {
-    pub data: DashMap<String, KSMFile>,
+pub struct KSMData {
+    pub data: DashMap<KsmFileKey, KSMFile>,
+    /// Compressed and archived files keyed by article number and variant, then by their full path
+    history: DashMap<KsmFileKey, BTreeMap<PathBuf, KSMFile>>,
+    history_loader: Option<ReaderParseFunction>,
+    /// Current file and history of the keys with history, merged once per change by `sync_data`
+    merged: DashMap<KsmFileKey, DataFrame>,
+    /// Modification time of files that failed to parse, so they are only retried when modified
+    failed: DashMap<PathBuf, SystemTime>,
+    /// Modification time of files left out by the discovery, so they are only logged once
+    skipped: DashMap<PathBuf, SystemTime>,
     dir_path: String,
-    file_extension: &'a str,
-    parse_function: fn(file_path: PathBuf) -> Result<DataFrame, ParseError>,
+    discovery: Discovery,
+    loader: Loader,
+    options: ParseOptions,
 }
-impl<'a> KSMData<'a> {
-    /// Creates a new instance of KSMData.
+impl KSMData {
+    /// Creates a new instance of KSMData loading the files found by `discovery` in `dir_path`.
     pub fn new(
         dir_path: String,
-        file_extension: &'a str,
-        parse_function: fn(file_path: PathBuf) -> Result<DataFrame, ParseError>,
+        discovery: Discovery,
+        loader: Loader,
+        options: ParseOptions,
     ) -> Self {
         KSMData {
             data: DashMap::new(),
+            history: DashMap::new(),
+            history_loader: None,
+            merged: DashMap::new(),
+            failed: DashMap::new(),
+            skipped: DashMap::new(),
             dir_path,
-            file_extension,
-            parse_function,
+            discovery,
+            loader,
+            options,
+        }
+    }
+
+    /// Returns the options the files are parsed with.
+    pub fn options(&self) -> &ParseOptions {
+        &self.options
+    }
+
+    /// Loads the compressed and archived files found by the discovery with `parse_function`.
+    ///
+    /// These files hold rotated history. They are always parsed in full and are served together
+    /// with the current file of the same key by `dataframe`. Without a history loader they are
+    /// ignored.
+    pub fn with_history(mut self, parse_function: ReaderParseFunction) -> Self {
+        self.history_loader = Some(parse_function);
+        self
+    }
+
+    /// Loads a modified file with the configured loader.
+    ///
+    /// Incremental loaders continue from the cursor stored for `key` and append the new
+    /// entries to the stored data frame, unless the parser reports that the file was rewritten.
+    fn load_file(
+        &self,
+        key: KsmFileKey,
+        file_name: &str,
+        path: PathBuf,
+        modified: SystemTime,
+    ) -> Result<KSMFile, ParseError> {
+        match self.loader {
+            Loader::Full(parse_function) => Ok(KSMFile {
+                dataframe: parse_function(path.clone(), &self.options)?,
+                path,
+                modified,
+                cursor: None,
+            }),
+            Loader::Incremental(parse_function) => {
+                let previous = self
+                    .data
+                    .get(&key)
+                    .filter(|ksmfile| ksmfile.path == path)
+                    .and_then(|ksmfile| ksmfile.cursor.clone())
+                    .unwrap_or_default();
+
+                let (update, cursor, report) =
+                    parse_function(path.clone(), &previous, &self.options)?;
+                for issue in &report.issues {
+                    log::warn!("{}", issue);
+                }
+                let dataframe = match update {
+                    DatUpdate::Full(dataframe) => {
+                        log::info!("Loading {}...", file_name);
+                        dataframe
+                    }
+                    DatUpdate::Appended(new_entries) => {
+                        log::info!(
+                            "Appending {} rows to {}...",
+                            new_entries.height(),
+                            file_name
+                        );
+                        match self.data.get(&key) {
+                            Some(ksmfile) => {
+                                // Entries of an unterminated end of the file are parsed again
+                                let pending = previous.pending_entries();
+                                let committed = ksmfile
+                                    .dataframe
+                                    .slice(0, ksmfile.dataframe.height().saturating_sub(pending));
+                                let (dataframe, report) =
+                                    append_entries_with_report(&committed, new_entries)?;
+                                for issue in &report.issues {
+                                    log::warn!("{}: {}", file_name, issue);
+                                }
+                                dataframe
+                            }
+                            None => new_entries,
+                        }
+                    }
+                };
+
+                Ok(KSMFile {
+                    dataframe,
+                    path,
+                    modified,
+                    cursor: Some(cursor),
+                })
+            }
         }
     }
 
     /// Loads data frames from files in the specified directory and stores them in the concurrent map.
     ///
-    /// This function reads the directory specified by `dir_path`, checks each file for the specified `file_extension`,
-    /// ensures that the filename follows a specific pattern and parses the file if it is modified more recently than
-    /// the stored version. The parsed data frame is stored in a concurrent map with the file name as the key.
+    /// This function lists the files in the directory specified by `dir_path` with the configured discovery and
+    /// parses each file if it is modified more recently than the stored version. The modified files are parsed
+    /// in parallel and stored in a concurrent map keyed by the article number and variant.
+    /// Files with an incremental loader only have their newly appended entries parsed, and are parsed again
+    /// while they end with an incomplete entry, so that the entry is loaded once the file stops growing.
+    ///
+    /// A file that cannot be parsed is logged and skipped, keeping its previously stored version. It is not
+    /// parsed again until it is modified. Files left out by the discovery, e.g. because another file has the
+    /// same article number and variant, are logged once and skipped until the discovery accepts them. The data
+    /// stored for such a key is dropped, and a file replacing the stored file under its key is loaded in full.
+    /// History files that are no longer found are dropped, and the history of their key is merged again.
     ///
     /// # Returns
-    /// A `Result` which is `Ok(())` if all files are processed successfully, or a `ParseError` if any error occurs.
+    /// A `Result` which is `Ok(())` if the directory could be listed, or a `ParseError` otherwise.
     pub async fn sync_data(&self, stop: Arc<AtomicBool>) -> Result<(), ParseError> {
-        //Compile regex pattern for filename
-        let pattern_string = format!(r"^\d{{3,5}}(-\d)?\.{}$", regex::escape(self.file_extension));
-        let filename_pattern = Regex::new(&pattern_string).map_err(|_| ParseError::InvalidRegex)?;
-
-        for entry in fs::read_dir(&self.dir_path).map_err(|_| ParseError::ReadFolderError)? {
-            if stop.load(Ordering::Relaxed) {
-                break;
+        let discovered = self.discovery.discover(&self.dir_path)?;
+        let mut changed_keys = Vec::new();
+        self.skipped.retain(|path, _| {
+            discovered
+                .failed
+                .iter()
+                .any(|failure| &failure.path == path)
+        });
+        for failure in discovered.failed {
+            // The stored data of an ambiguous key may belong to either file, so it is no longer served
+            if let ParseError::DuplicateFileKey { key, .. } = &failure.error {
+                if self.data.remove(key).is_some() {
+                    log::warn!("Unloading {}, as several files have this key", key);
+                    changed_keys.push(*key);
+                }
             }
+            let logged = self
+                .skipped
+                .get(&failure.path)
+                .is_some_and(|modified| *modified == failure.modified);
+            if !logged {
+                log::error!("Skipping {}: {:#}", failure.path.display(), failure.error);
+                self.skipped.insert(failure.path, failure.modified);
+            }
+        }
 
-            let entry = entry.map_err(|_| ParseError::ReadFolderError)?;
-            let current_entry_modified = entry
-                .metadata()
-                .map_err(|_| ParseError::ReadMetadataError)?
-                .modified()
-                .map_err(|_| ParseError::ReadMetadataError)?;
-
-            let path = entry.path();
+        // History files that were deleted or are no longer discovered are no longer served
+        let archived: HashSet<PathBuf> = discovered
+            .files
+            .iter()
+            .filter(|file| file.is_archived())
+            .map(DiscoveredFile::full_path)
+            .collect();
+        self.history.retain(|key, sources| {
+            sources.retain(|path, _| {
+                let found = archived.contains(path);
+                if !found {
+                    log::info!("Unloading {}, as it is no longer found", path.display());
+                    changed_keys.push(*key);
+                }
+                found
+            });
+            !sources.is_empty()
+        });
 
-            if let Some(file_name) = path.file_name().and_then(|name| name.to_str()) {
-                if filename_pattern.is_match(file_name) {
-                    let stored_entry_modified = match self.data.get(file_name) {
-                        Some(ksmfile) => ksmfile.modified,
-                        None => SystemTime::UNIX_EPOCH,
-                    };
+        let modified_files: Vec<DiscoveredFile> = discovered
+            .files
+            .into_iter()
+            .filter(|file| !file.is_archived() || self.history_loader.is_some())
+            .filter(|file| {
+                let stored_entry_modified = if file.is_archived() {
+                    self.history
+                        .get(&file.key)
+                        .and_then(|sources| sources.get(&file.full_path()).map(|f| f.modified))
+                } else {
+                    self.data
+                        .get(&file.key)
+                        .filter(|ksmfile| ksmfile.path == file.path)
+                        .map(|ksmfile| ksmfile.modified)
+                };
+                let stored_entry_modified = stored_entry_modified.unwrap_or(SystemTime::UNIX_EPOCH);
+                let failed_modified = match self.failed.get(&file.full_path()) {
+                    Some(modified) => *modified,
+                    None => SystemTime::UNIX_EPOCH,
+                };
+                // A trailing entry left incomplete is parsed once the file stops growing
+                let pending = !file.is_archived()
+                    && self.data.get(&file.key).is_some_and(|ksmfile| {
+                        ksmfile.path == file.path
+                            && ksmfile.cursor.as_ref().is_some_and(DatCursor::has_pending)
+                    });
+                (file.modified > stored_entry_modified || pending)
+                    && file.modified > failed_modified
+            })
+            .collect();
 
-                    // Parse and store the file if it is modified more recently
-                    if current_entry_modified > stored_entry_modified {
-                        log::info!("Loading {}...", file_name);
-                        let parse_function = self.parse_function;
-                        let data_frame = parse_function(path.clone())?;
-                        let ksm_file_entry = KSMFile {
-                            dataframe: data_frame.clone(),
-                            modified: current_entry_modified,
-                        };
-                        self.data.insert(file_name.to_owned(), ksm_file_entry);
+        // Parse and store the files that are modified more recently
+        let loaded_keys: Vec<KsmFileKey> = modified_files
+            .into_par_iter()
+            .filter_map(|file| {
+                if stop.load(Ordering::Relaxed) {
+                    return None;
+                }
+                let full_path = file.full_path();
+                let result = match self.history_loader {
+                    Some(parse_function) if file.is_archived() => {
+                        log::info!("Loading {}...", full_path.display());
+                        file.open()
+                            .and_then(|reader| parse_function(reader, &self.options))
+                            .map(|dataframe| {
+                                let ksm_file_entry = KSMFile {
+                                    dataframe,
+                                    path: full_path.clone(),
+                                    modified: file.modified,
+                                    cursor: None,
+                                };
+                                self.history
+                                    .entry(file.key)
+                                    .or_default()
+                                    .insert(full_path.clone(), ksm_file_entry);
+                            })
+                    }
+                    _ => {
+                        if let Loader::Full(_) = self.loader {
+                            log::info!("Loading {}...", file.file_name);
+                        }
+                        self.load_file(file.key, &file.file_name, file.path.clone(), file.modified)
+                            .map(|ksm_file_entry| {
+                                self.data.insert(file.key, ksm_file_entry);
+                            })
+                    }
+                };
+                match result {
+                    Ok(()) => {
+                        self.failed.remove(&full_path);
+                        Some(file.key)
                     }
+                    Err(e) => {
+                        log::error!("Skipping {}: {:#}", full_path.display(), e);
+                        self.failed.insert(full_path, file.modified);
+                        None
+                    }
+                }
+            })
+            .collect();
+        changed_keys.extend(loaded_keys);
+        changed_keys.sort();
+        changed_keys.dedup();
+
+        // Merge the history of the changed keys once, instead of on every request
+        for key in changed_keys {
+            match self.merge_history(&key) {
+                Ok(Some(dataframe)) => {
+                    self.merged.insert(key, dataframe);
+                }
+                Ok(None) => {
+                    self.merged.remove(&key);
+                }
+                Err(e) => {
+                    log::error!("Error combining measurement history of {}: {:#}", key, e);
+                    self.merged.remove(&key);
                 }
-            } else {
-                return Err(ParseError::FileNameExtractionError);
             }
         }
         Ok(())
     }
+
+    /// Returns the data of a key, with the rows of its history files before the rows of the
+    /// current file, ordered by `measure_time1970` if there is any history.
+    ///
+    /// The history is merged by `sync_data`. If merging failed, only the current file is returned.
+    ///
+    /// # Returns
+    /// `None` if neither a current file nor any history is loaded for the key.
+    pub fn dataframe(&self, key: &KsmFileKey) -> Option<DataFrame> {
+        match self.merged.get(key) {
+            Some(merged) => Some(merged.clone()),
+            None => self.data.get(key).map(|ksmfile| ksmfile.dataframe.clone()),
+        }
+    }
+
+    /// Merges the current file of a key with its history.
+    ///
+    /// # Returns
+    /// `Ok(None)` if the key has no history.
+    fn merge_history(&self, key: &KsmFileKey) -> Result<Option<DataFrame>, ParseError> {
+        let current = self.data.get(key).map(|ksmfile| ksmfile.dataframe.clone());
+        let history = match self.history.get(key) {
+            Some(sources) if !sources.is_empty() => sources,
+            _ => return Ok(None),
+        };
+
+        let mut dataframe = DataFrame::default();
+        for ksmfile in history.values() {
+            dataframe = append_entries(&dataframe, ksmfile.dataframe.clone())?;
+        }
+        if let Some(current) = current {
+            dataframe = append_entries(&dataframe, current)?;
+        }
+        if dataframe.get_column_index("measure_time1970").is_some() {
+            dataframe = dataframe
+                .sort(
+                    ["measure_time1970"],
+                    SortMultipleOptions::default().with_maintain_order(true),
+                )
+                .map_err(|source| ParseError::DataAlignmentError {
+                    location: Location::default(),
+                    source,
+                })?;
+        }
+        Ok(Some(dataframe))
+    }
+
+    /// Returns the keys of all loaded variants of an article, in order.
+    pub fn variants(&self, article: u32) -> Vec<KsmFileKey> {
+        self.keys()
+            .into_iter()
+            .filter(|key| key.article == article)
+            .collect()
+    }
+
+    /// Returns the keys with a current file or history, in order.
+    pub fn keys(&self) -> Vec<KsmFileKey> {
+        let mut keys: Vec<KsmFileKey> = self
+            .data
+            .iter()
+            .map(|entry| *entry.key())
+            .chain(self.history.iter().map(|entry| *entry.key()))
+            .collect();
+        keys.sort();
+        keys.dedup();
+        keys
+    }
+
+    /// Returns a summary of the loaded data of every key, in key order. The current file and
+    /// the history of a key are summarized together, the way `dataframe` combines them.
+    pub fn summaries(&self) -> Vec<KSMFileSummary> {
+        self.keys()
+            .into_iter()
+            .map(|key| {
+                let mut summary = KSMFileSummary::new(key);
+                if let Some(sources) = self.history.get(&key) {
+                    sources.values().for_each(|ksmfile| summary.add(ksmfile));
+                }
+                if let Some(ksmfile) = self.data.get(&key) {
+                    summary.add(&ksmfile);
+                }
+                summary
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ksmparser::measurement::parse_dat_file;
+    use std::fs;
+
+    #[test]
+    fn summarize_loaded_files() {
+        let dir = env::temp_dir().join(format!("ksmserver_summaries_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("00202.dat"),
+            "\"measure_time1970\"\t\"wall_min\"\n1700000060\t0.21\n\
+            \"measure_time1970\"\t\"wall_min\"\t\"info5\"\n1700000000\t0.22\tAnna\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("00202-1.dat"),
+            "\"measure_time1970\"\t\"wall_mean\"\n1700000120\t0.3\n",
+        )
+        .unwrap();
+        fs::write(dir.join("notes.txt"), "Not a measurement").unwrap();
+
+        let data = KSMData::new(
+            dir.to_string_lossy().into_owned(),
+            Discovery::new("dat").unwrap(),
+            Loader::Full(parse_dat_file),
+            ParseOptions::default(),
+        );
+        async_std::task::block_on(data.sync_data(Arc::new(AtomicBool::new(false)))).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let summaries = data.summaries();
+        let keys: Vec<String> = summaries.iter().map(|s| s.key.to_string()).collect();
+        assert_eq!(keys, ["00202", "00202-1"]);
+        assert_eq!(data.variants(202).len(), 2);
+        assert!(data.variants(203).is_empty());
+
+        let summary = &summaries[0];
+        assert_eq!((summary.article, summary.variant), (202, None));
+        assert_eq!(summary.rows, 2);
+        // Columns are listed in order of first appearance
+        assert_eq!(
+            summary.columns,
+            ["measure_time1970", "wall_min", "info5", "local_time"]
+        );
+        assert_eq!(summary.first_measure_time, Some(1700000000));
+        assert_eq!(summary.last_measure_time, Some(1700000060));
+        assert_eq!(summaries[1].variant, Some(1));
+        assert_eq!(summaries[1].rows, 1);
+    }
 }
@@ -1,32 +1,37 @@
 use async_std::task;
-use chrono::NaiveDate;
-use ksmparser::article::parse_art_file;
-use ksmparser::measurement::parse_dat_file;
-use ksmserver::{AppState, Environment, KSMData, KSMError};
+use chrono::{DateTime, FixedOffset, NaiveDate};
+use ksmparser::aggregate::{aggregate, Aggregation, DEFAULT_AGGREGATIONS};
+use ksmparser::article::{parse_art_file, Article};
+use ksmparser::checkresult::{CheckResultMapping, CHECKRESULT_COLUMN};
+use ksmparser::compliance::evaluate_limits;
+use ksmparser::filter::parse_filter;
+use ksmparser::key::KsmFileKey;
+use ksmparser::measurement::{append_entries, parse_dat_file_from, parse_dat_reader};
+use ksmparser::statistics::{article_capability, DEFAULT_COLUMNS, DEFAULT_PERCENTILES};
+use ksmserver::output::{parse_delimiter, stream_dataframe, CsvOptions, OutputFormat};
+use ksmserver::{AppState, Environment, KSMData, KSMError, KSMFileSummary, Loader};
 use polars::prelude::*;
-use polars_io::json::JsonWriter;
 use serde::Deserialize;
-use std::io::Cursor;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::time;
-use tide::{log, Request, Response, StatusCode};
+use tide::{log, Body, Request, Response, StatusCode};
 //use tikv_jemallocator::Jemalloc;
 
 //#[global_allocator]
 //static GLOBAL: Jemalloc = Jemalloc;
 
-async fn sync_task<'a>(
+async fn sync_task(
     stop: Arc<AtomicBool>,
-    measurement_data: Arc<KSMData<'a>>,
-    parameter_data: Arc<KSMData<'a>>,
+    measurement_data: Arc<KSMData>,
+    parameter_data: Arc<KSMData>,
 ) {
     log::info!("Startup: Entering sync task");
     while !stop.load(Ordering::Relaxed) {
         if let Err(e) = measurement_data.sync_data(stop.clone()).await {
-            log::error!("Error when syncing measurement data: {}", e);
+            log::error!("Error when syncing measurement data: {:#}", e);
         }
         if let Err(e) = parameter_data.sync_data(stop.clone()).await {
-            log::error!("Error when syncing parameter data: {}", e);
+            log::error!("Error when syncing parameter data: {:#}", e);
         }
         task::sleep(time::Duration::from_secs(2)).await;
     }
@@ -52,7 +57,13 @@ async fn main() -> tide::Result<()> {
     tide::log::start();
 
     // Read environment variables
-    let env = Environment::new();
+    let env = match Environment::new() {
+        Ok(env) => env,
+        Err(e) => {
+            log::error!("{:#}", e);
+            return Ok(());
+        }
+    };
 
     // Create stop flag
     let stop_flag = match create_stop_flag() {
@@ -60,8 +71,21 @@ async fn main() -> tide::Result<()> {
         None => return Ok(()),
     };
     // Create KSMData structs for measurement and parameter data
-    let art_data = Arc::new(KSMData::new(env.art_path, "art", parse_art_file));
-    let meas_data = Arc::new(KSMData::new(env.dat_path, "dat", parse_dat_file));
+    let art_data = Arc::new(KSMData::new(
+        env.art_path,
+        env.art_discovery,
+        Loader::Full(parse_art_file),
+        env.parse_options.clone(),
+    ));
+    let meas_data = Arc::new(
+        KSMData::new(
+            env.dat_path,
+            env.dat_discovery,
+            Loader::Incremental(parse_dat_file_from),
+            env.parse_options,
+        )
+        .with_history(parse_dat_reader),
+    );
 
     //Start data sync task
     let sync_task_handle = task::spawn(sync_task(
@@ -74,6 +98,7 @@ async fn main() -> tide::Result<()> {
     let state = AppState {
         measurement_data: meas_data.clone(),
         parameter_data: art_data.clone(),
+        csv: env.csv,
     };
 
     //Create server object
@@ -81,8 +106,23 @@ async fn main() -> tide::Result<()> {
     server.with(tide::log::LogMiddleware::new());
 
     //Setup endpoints
+    server.at("/measurement").get(list_measurements);
     server.at("/measurement/:name").get(measurement);
+    server
+        .at("/measurement/:name/variants")
+        .get(measurement_variants);
+    server
+        .at("/measurement/:name/compliance")
+        .get(measurement_compliance);
+    server
+        .at("/measurement/:name/aggregate")
+        .get(measurement_aggregate);
+    server.at("/stats/:name").get(stats);
+    server.at("/parameters").get(list_parameters);
     server.at("/parameters/:name").get(parameters);
+    server
+        .at("/parameters/:name/variants")
+        .get(parameter_variants);
     server
         .at("/views/parameter_resistance")
         .get(view_parameter_resistance);
@@ -91,7 +131,7 @@ async fn main() -> tide::Result<()> {
         .get(view_operator_measurement);
 
     //Start server
-    let _ = task::spawn(server.listen(env.bind_addr));
+    drop(task::spawn(server.listen(env.bind_addr)));
 
     //Wait for sync task to finish for graceful exit
     sync_task_handle.await;
@@ -101,31 +141,77 @@ async fn main() -> tide::Result<()> {
     Ok(())
 }
 
-fn dataframe_to_json_response(dataframe: &mut DataFrame) -> tide::Response {
-    // Create a buffer using a cursor over a new, empty vector to temporarily store the JSON data.
-    let mut buf = Cursor::new(Vec::new());
-    //
-    // Attempt to write the DataFrame to the buffer as JSON. If this fails,
-    // return a 500 Internal Server Error response.
-    if JsonWriter::new(&mut buf).finish(dataframe).is_err() {
-        return plain_response(StatusCode::InternalServerError, "Failed to write JSON");
-    }
+// Defines a structure to parse the output query parameters shared by all endpoints returning
+// DataFrames.
+#[derive(Deserialize, Debug)]
+struct OutputQuery {
+    format: Option<String>, // Optional output format, takes precedence over the Accept header
+    delimiter: Option<String>, // Optional CSV delimiter, e.g. ; or tab
+    decimal_comma: Option<bool>, // Optionally write CSV floats with a decimal comma
+}
 
-    // Convert the buffer into a String. This is done by first obtaining the Vec<u8>
-    // (byte vector) inside the buffer, then attempting to create a UTF-8 string from it.
-    let json = match String::from_utf8(buf.into_inner()) {
-        Ok(data) => data,
-        Err(_) => {
-            return plain_response(StatusCode::InternalServerError, "Found invalid UTF-8");
+/// Output format and CSV settings of a response
+struct Output {
+    format: OutputFormat,
+    csv: CsvOptions,
+}
+
+/// Reads the output format from the `format` query parameter or the `Accept` header, and the
+/// CSV settings from the query parameters or the server defaults.
+///
+/// An `Accept` header without a supported media type falls back to JSON, as most clients
+/// sending one, e.g. browsers, can still read it. Returns the status and message of the error
+/// response if the `format` parameter or the delimiter is invalid.
+fn requested_output(req: &Request<AppState>) -> Result<Output, (StatusCode, String)> {
+    let query: OutputQuery = req
+        .query()
+        .map_err(|e| (StatusCode::BadRequest, e.to_string()))?;
+
+    let format = match (query.format, req.header("Accept")) {
+        (Some(name), _) => name
+            .parse::<OutputFormat>()
+            .map_err(|e| (StatusCode::BadRequest, e))?,
+        (None, Some(accept)) => {
+            let accept = accept
+                .iter()
+                .map(|value| value.as_str())
+                .collect::<Vec<&str>>()
+                .join(",");
+            OutputFormat::from_accept(&accept).unwrap_or_default()
         }
+        (None, None) => OutputFormat::default(),
     };
 
-    // If everything was successful and the JSON data is valid, return a 200 OK response
-    // with the JSON data as the body, and set the content type to application/json.
-    Response::builder(StatusCode::Ok)
-        .body(json)
-        .content_type(tide::http::mime::JSON)
-        .build()
+    let mut csv = req.state().csv;
+    if let Some(delimiter) = query.delimiter {
+        csv.delimiter = parse_delimiter(&delimiter).map_err(|e| (StatusCode::BadRequest, e))?;
+    }
+    if let Some(decimal_comma) = query.decimal_comma {
+        csv.decimal_comma = decimal_comma;
+    }
+    Ok(Output { format, csv })
+}
+
+/// Streams the DataFrame in the requested output format. Formats other than JSON are sent as
+/// an attachment named `<filename>.<extension>`.
+fn dataframe_response(dataframe: DataFrame, output: &Output, filename: &str) -> tide::Response {
+    let reader = stream_dataframe(dataframe, output.format, output.csv);
+    let response = Response::builder(StatusCode::Ok)
+        .body(Body::from_reader(reader, None))
+        .content_type(output.format.content_type());
+    match output.format {
+        OutputFormat::Json => response.build(),
+        format => response
+            .header(
+                "Content-Disposition",
+                format!(
+                    "attachment; filename=\"{}.{}\"",
+                    filename,
+                    format.extension()
+                ),
+            )
+            .build(),
+    }
 }
 
 fn plain_response(code: StatusCode, msg: &str) -> tide::Response {
@@ -166,12 +252,18 @@ fn filter_dataframe_by_measure_time(
     // Convert the end date to a timestamp at the end of the day (one second before midnight)
     let end = naive_date_to_epoch(&end_date, 23, 59, 59)?;
     log::info!("start: {} end: {}", start, end);
-    // Create filter expressions for data after the start date and before the end date
+    Ok(filter_dataframe_by_epoch_range(lazyframe, start, end))
+}
+
+/// Keeps the rows with a `measure_time1970` between `start` and `end` seconds since the epoch,
+/// both inclusive.
+fn filter_dataframe_by_epoch_range(lazyframe: LazyFrame, start: i64, end: i64) -> LazyFrame {
+    // Create filter expressions for data after the start and before the end
     let start = col("measure_time1970").gt_eq(start);
     let end = col("measure_time1970").lt_eq(end);
 
     // Apply filter and lazily load results
-    Ok(lazyframe.filter(start.and(end)))
+    lazyframe.filter(start.and(end))
 }
 
 fn select_dataframe_columns(lazyframe: LazyFrame, columns: &str) -> Result<DataFrame, PolarsError> {
@@ -190,48 +282,383 @@ fn select_dataframe_columns(lazyframe: LazyFrame, columns: &str) -> Result<DataF
     lazyframe.select(column_expressions).collect()
 }
 
-// Defines a structure to parse query parameters from a request.
+/// Applies an optional row filter, e.g. `wall_min<0.2 and info5=="Anna"`, to a LazyFrame.
+///
+/// Returns the status and message of the error response if the filter is invalid.
+fn filter_rows(
+    mut lazyframe: LazyFrame,
+    filter: Option<&str>,
+) -> Result<LazyFrame, (StatusCode, String)> {
+    let filter = match filter {
+        Some(filter) if !filter.trim().is_empty() => filter,
+        _ => return Ok(lazyframe),
+    };
+    let schema = lazyframe
+        .collect_schema()
+        .map_err(|e| (StatusCode::InternalServerError, e.to_string()))?;
+    match parse_filter(filter, &schema) {
+        Ok(expr) => Ok(lazyframe.filter(expr)),
+        Err(e) => Err((StatusCode::BadRequest, e.to_string())),
+    }
+}
+
+/// Parses a `sort` query parameter of comma-separated `column[:asc|desc]` entries into the
+/// column names and their descending flags.
+fn parse_sort(sort: &str) -> Result<(Vec<PlSmallStr>, Vec<bool>), String> {
+    sort.split(',')
+        .map(|entry| {
+            let (column, order) = match entry.split_once(':') {
+                Some((column, order)) => (column, order),
+                None => (entry, "asc"),
+            };
+            let descending = match order {
+                "asc" => false,
+                "desc" => true,
+                _ => {
+                    return Err(format!(
+                        "Invalid sort order '{}', expected asc or desc",
+                        order
+                    ))
+                }
+            };
+            if column.is_empty() {
+                return Err("Missing sort column".to_string());
+            }
+            Ok((PlSmallStr::from_str(column), descending))
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map(|entries| entries.into_iter().unzip())
+}
+
+/// Counts the rows of a LazyFrame without collecting its columns.
+fn count_rows(lazyframe: LazyFrame) -> Result<u64, PolarsError> {
+    let dataframe = lazyframe.select([len()]).collect()?;
+    let count = dataframe.get_columns()[0].cast(&DataType::UInt64)?;
+    Ok(count.u64()?.get(0).unwrap_or(0))
+}
+
+/// Parses the `name` parameter of a request as a file key.
+/// Accepts article numbers like `202` as well as file names like `00202-1.dat`.
+fn file_key(req: &Request<AppState>) -> Option<KsmFileKey> {
+    req.param("name").ok()?.parse().ok()
+}
+
+// Defines a structure to parse the time range query parameters shared by the endpoints
+// returning the measurements of a key.
 #[derive(Deserialize, Debug)]
-struct MeasurementQuery {
+struct TimeRangeQuery {
     start_date: Option<NaiveDate>, // Optional start date for filtering dataframe
     end_date: Option<NaiveDate>,   // Optional end date for filtering dataframe
-    columns: Option<String>,       // Optional comma-separated string of columns to select
+    start: Option<DateTime<FixedOffset>>, // Optional start time, e.g. 2023-11-15T06:00:00Z
+    end: Option<DateTime<FixedOffset>>, // Optional end time, e.g. 2023-11-15T14:00:00Z
+}
+
+impl TimeRangeQuery {
+    /// Returns the first and last `measure_time1970` to return. The `start` and `end` times take
+    /// precedence over the whole days of `start_date` and `end_date`.
+    fn time_range(&self) -> Result<(i64, i64), KSMError> {
+        let start = match self.start {
+            Some(start) => start.timestamp(),
+            None => naive_date_to_epoch(&self.start_date.unwrap_or(NaiveDate::MIN), 0, 0, 0)?,
+        };
+        let end = match self.end {
+            Some(end) => end.timestamp(),
+            None => naive_date_to_epoch(&self.end_date.unwrap_or(NaiveDate::MAX), 23, 59, 59)?,
+        };
+        Ok((start, end))
+    }
+}
+
+/// Looks up the measurements of the key given by the `name` parameter, including the history
+/// of the key, and keeps the rows within the requested time range.
+///
+/// Returns the key and the rows, or the status and message of the error response if the key is
+/// invalid, no measurements of the key are loaded or the time range is invalid.
+fn requested_measurements(
+    req: &Request<AppState>,
+) -> Result<(KsmFileKey, LazyFrame), (StatusCode, String)> {
+    let query: TimeRangeQuery = req
+        .query()
+        .map_err(|e| (StatusCode::BadRequest, e.to_string()))?;
+
+    let key = file_key(req).ok_or_else(|| {
+        log::error!("Invalid key for {} request", req.url().path());
+        // The name parameter is missing or not a valid key
+        (StatusCode::BadRequest, "Invalid key".to_string())
+    })?;
+
+    let dataframe = req
+        .state()
+        .measurement_data
+        .dataframe(&key)
+        .ok_or_else(|| {
+            log::error!("Invalid measurement entry requested: {}", key);
+            (
+                StatusCode::InternalServerError,
+                format!("Measurement file not found: {}", key),
+            )
+        })?;
+
+    // Filter the dataframe by measure time using the provided time range
+    let (start, end) = query
+        .time_range()
+        .map_err(|e| (StatusCode::InternalServerError, e.to_string()))?;
+    Ok((
+        key,
+        filter_dataframe_by_epoch_range(dataframe.lazy(), start, end),
+    ))
+}
+
+/// Lists the keys of all variants of the article given by the `name` parameter.
+async fn variants(req: Request<AppState>, data: &KSMData) -> tide::Result {
+    let key = match file_key(&req) {
+        Some(key) => key,
+        None => return Ok(plain_response(StatusCode::BadRequest, "Invalid key")),
+    };
+
+    match serde_json::to_string(&data.variants(key.article)) {
+        Ok(json) => Ok(Response::builder(StatusCode::Ok)
+            .body(json)
+            .content_type(tide::http::mime::JSON)
+            .build()),
+        Err(_) => Ok(plain_response(
+            StatusCode::InternalServerError,
+            "Error converting to json",
+        )),
+    }
+}
+
+// Defines a structure to parse query parameters from a request.
+#[derive(Deserialize, Debug)]
+struct ListQuery {
+    prefix: Option<String>, // Optional prefix of the article numbers to list, e.g. 20 for 202 and 2031
+}
+
+/// Lists a summary of every loaded key: modification time, row count, column names and the
+/// first and last measure time.
+async fn list(req: Request<AppState>, data: &KSMData) -> tide::Result {
+    let query: ListQuery = req.query()?;
+    // Zero padding is ignored, so 00202 matches the same articles as 202
+    let prefix = query.prefix.unwrap_or_default();
+    let prefix = prefix.trim().trim_start_matches('0');
+    if !prefix.bytes().all(|b| b.is_ascii_digit()) {
+        return Ok(plain_response(StatusCode::BadRequest, "Invalid prefix"));
+    }
+
+    let summaries: Vec<KSMFileSummary> = data
+        .summaries()
+        .into_iter()
+        .filter(|summary| summary.article.to_string().starts_with(prefix))
+        .collect();
+
+    match serde_json::to_string(&summaries) {
+        Ok(json) => Ok(Response::builder(StatusCode::Ok)
+            .body(json)
+            .content_type(tide::http::mime::JSON)
+            .build()),
+        Err(_) => Ok(plain_response(
+            StatusCode::InternalServerError,
+            "Error converting to json",
+        )),
+    }
+}
+
+async fn list_measurements(req: Request<AppState>) -> tide::Result {
+    let data = req.state().measurement_data.clone();
+    list(req, &data).await
 }
-async fn measurement(req: Request<AppState<'_>>) -> tide::Result {
+
+async fn list_parameters(req: Request<AppState>) -> tide::Result {
+    let data = req.state().parameter_data.clone();
+    list(req, &data).await
+}
+
+async fn measurement_variants(req: Request<AppState>) -> tide::Result {
+    let data = req.state().measurement_data.clone();
+    variants(req, &data).await
+}
+
+async fn parameter_variants(req: Request<AppState>) -> tide::Result {
+    let data = req.state().parameter_data.clone();
+    variants(req, &data).await
+}
+
+// Defines a structure to parse query parameters from a request.
+#[derive(Deserialize, Debug)]
+struct MeasurementQuery {
+    columns: Option<String>, // Optional comma-separated string of columns to select
+    filter: Option<String>,  // Optional row filter, e.g. wall_min<0.2 and info5=="Anna"
+}
+
+// Defines a structure to parse the paging query parameters of `/measurement/:name`.
+#[derive(Deserialize, Debug)]
+struct PageQuery {
+    sort: Option<String>, // Optional sort order, comma-separated column[:asc|desc] entries
+    offset: Option<u64>,  // Optional number of rows to skip
+    limit: Option<u64>,   // Optional maximum number of rows to return
+    tail: Option<u64>,    // Optional number of rows to return from the end, e.g. the latest rows
+}
+
+impl PageQuery {
+    /// Returns the rows of the page of a LazyFrame with `total` rows: the last `tail` rows, or
+    /// up to `limit` rows after the first `offset` rows. Pages past the end are empty.
+    fn slice(&self, lazyframe: LazyFrame, total: u64) -> LazyFrame {
+        let (offset, limit) = match self.tail {
+            Some(tail) => (total.saturating_sub(tail), tail),
+            None => (self.offset.unwrap_or(0), self.limit.unwrap_or(u64::MAX)),
+        };
+        lazyframe.slice(
+            i64::try_from(offset).unwrap_or(i64::MAX),
+            IdxSize::try_from(limit).unwrap_or(IdxSize::MAX),
+        )
+    }
+}
+
+/// Returns the rows of a measurement file within a time range, optionally reduced to the rows
+/// matching `filter`. The time range is given by `start` and `end` times or by the whole days
+/// of `start_date` and `end_date`. The rows can be sorted and paged with `sort`, `offset` and
+/// `limit`, or reduced to the last rows with `tail`. The number of matching rows before paging
+/// is sent in the `X-Total-Count` header.
+async fn measurement(req: Request<AppState>) -> tide::Result {
     //Deserialize the query parameters into the MeasurementQuery struct
     let query: MeasurementQuery = req.query()?;
-    let data = &req.state().measurement_data;
+    let page: PageQuery = req.query()?;
+    let output = match requested_output(&req) {
+        Ok(output) => output,
+        Err((code, msg)) => return Ok(plain_response(code, &msg)),
+    };
 
-    let key = match req.param("name") {
-        Ok(file) => file,
-        Err(_) => {
-            log::error!("Invalid key for measurement request");
-            // Return a BadRequest response if filename parameter is missing or incorrect
-            return Ok(plain_response(StatusCode::BadRequest, "Invalid key"));
+    if page.tail.is_some() && (page.offset.is_some() || page.limit.is_some()) {
+        return Ok(plain_response(
+            StatusCode::BadRequest,
+            "tail cannot be combined with offset or limit",
+        ));
+    }
+    let sort = match page.sort.as_deref().map(parse_sort).transpose() {
+        Ok(sort) => sort,
+        Err(e) => return Ok(plain_response(StatusCode::BadRequest, &e)),
+    };
+
+    let (key, lazyframe) = match requested_measurements(&req) {
+        Ok(measurements) => measurements,
+        Err((code, msg)) => return Ok(plain_response(code, &msg)),
+    };
+
+    // Keep the rows matching the optional filter
+    let lazyframe = match filter_rows(lazyframe, query.filter.as_deref()) {
+        Ok(lazyframe) => lazyframe,
+        Err((code, msg)) => return Ok(plain_response(code, &msg)),
+    };
+
+    // Count the matching rows before paging
+    let total = match count_rows(lazyframe.clone()) {
+        Ok(total) => total,
+        Err(e) => {
+            log::error!("Error counting measurement rows of {}: {:#}", key, e);
+            return Ok(plain_response(
+                StatusCode::InternalServerError,
+                "Error counting rows",
+            ));
         }
     };
 
-    let lazyframe = match data.data.get(key) {
-        Some(ksmfile) => ksmfile.dataframe.clone().lazy(),
+    // Sort before paging, so that pages and the tail follow the requested order
+    let lazyframe = match sort {
+        Some((columns, descending)) => lazyframe.sort(
+            columns,
+            SortMultipleOptions::default()
+                .with_order_descending_multi(descending)
+                .with_maintain_order(true),
+        ),
+        None => lazyframe,
+    };
+    let lazyframe = page.slice(lazyframe, total);
+
+    // Process the optional column filtering
+    let column_string = query.columns.unwrap_or_default();
+    let dataframe = match select_dataframe_columns(lazyframe, column_string.as_str()) {
+        Ok(df) => df,
+        Err(e) => match e {
+            PolarsError::ColumnNotFound(..) => {
+                // Return BadRequest if a specified or sorted column doesn't exist
+                return Ok(plain_response(StatusCode::BadRequest, "Column not found"));
+            }
+            _ => {
+                // Return InternalServerError for other column-related errors
+                return Ok(plain_response(
+                    StatusCode::InternalServerError,
+                    format!("Column errror {:?}", e.to_string()).as_str(),
+                ));
+            }
+        },
+    };
+
+    // Convert the final dataframe to the requested format and use it as the response
+    let filename = format!("{}_measurement", key);
+    let mut response = dataframe_response(dataframe, &output, &filename);
+    response.insert_header("X-Total-Count", total.to_string());
+    Ok(response)
+}
+
+// Defines a structure to parse the query parameters of the endpoints using article limits.
+#[derive(Deserialize, Debug)]
+struct LimitsQuery {
+    symmetric: Option<bool>, // Optionally mirror a missing limit around the nominal value
+}
+
+/// Returns the article, with a tolerance symmetric around the nominal values if the request
+/// asks for it with `symmetric=true`.
+fn requested_limits(req: &Request<AppState>, article: Article) -> tide::Result<Article> {
+    let query: LimitsQuery = req.query()?;
+    if query.symmetric.unwrap_or(false) {
+        Ok(article.with_symmetric_limits())
+    } else {
+        Ok(article)
+    }
+}
+
+/// Evaluates the measurements of a key against the minimum and maximum limits in the article
+/// parameters of the same key. Adds a `<name>_pass` column for every checked value and a
+/// `limit_violation` column, and accepts the same query parameters as `/measurement/:name`.
+/// Values with only a nominal value are unchecked, and values with only a minimum or maximum
+/// are checked on that side, unless `symmetric=true` mirrors it around the nominal value.
+async fn measurement_compliance(req: Request<AppState>) -> tide::Result {
+    let query: MeasurementQuery = req.query()?;
+    let output = match requested_output(&req) {
+        Ok(output) => output,
+        Err((code, msg)) => return Ok(plain_response(code, &msg)),
+    };
+    let (key, lazyframe) = match requested_measurements(&req) {
+        Ok(measurements) => measurements,
+        Err((code, msg)) => return Ok(plain_response(code, &msg)),
+    };
+
+    let article = match req.state().parameter_data.data.get(&key) {
+        Some(ksmfile) => match Article::from_dataframe(&ksmfile.dataframe) {
+            Ok(article) => article,
+            Err(e) => {
+                log::error!("Invalid parameters for {}: {:#}", key, e);
+                return Ok(plain_response(
+                    StatusCode::InternalServerError,
+                    "Invalid article parameters",
+                ));
+            }
+        },
         None => {
-            log::error!("Invalid measurement entry requested: {}", key);
-            let response_string = format!("Measurement file not found: {}", key);
+            log::error!("Invalid parameter entry requested: {}", key);
+            let response_string = format!("Parameter entry not found: {}", key);
             return Ok(plain_response(
                 StatusCode::InternalServerError,
                 response_string.as_str(),
             ));
         }
     };
+    let article = requested_limits(&req, article)?;
 
-    // Filter the dataframe by measure time using provided start and end dates
-    let lazyframe = match filter_dataframe_by_measure_time(
-        lazyframe,
-        query.start_date.unwrap_or(NaiveDate::MIN),
-        query.end_date.unwrap_or(NaiveDate::MAX),
-    ) {
+    // Only the requested time range is evaluated
+    let dataframe = match lazyframe.collect() {
         Ok(df) => df,
         Err(e) => {
-            // Return InternalServerError if there is an error in filtering
             return Ok(plain_response(
                 StatusCode::InternalServerError,
                 e.to_string().as_str(),
@@ -239,47 +666,242 @@ async fn measurement(req: Request<AppState<'_>>) -> tide::Result {
         }
     };
 
-    // Process the optional column filtering
+    let lazyframe = match evaluate_limits(&dataframe, &article) {
+        Ok(dataframe) => dataframe.lazy(),
+        Err(e) => {
+            log::error!("Error evaluating limits of {}: {:#}", key, e);
+            return Ok(plain_response(
+                StatusCode::InternalServerError,
+                "Error evaluating limits",
+            ));
+        }
+    };
+
+    // Keep the rows matching the optional filter, which may refer to the added columns
+    let lazyframe = match filter_rows(lazyframe, query.filter.as_deref()) {
+        Ok(lazyframe) => lazyframe,
+        Err((code, msg)) => return Ok(plain_response(code, &msg)),
+    };
+
     let column_string = query.columns.unwrap_or_default();
-    let mut dataframe = match select_dataframe_columns(lazyframe, column_string.as_str()) {
+    let dataframe = match select_dataframe_columns(lazyframe, column_string.as_str()) {
         Ok(df) => df,
-        Err(e) => match e {
-            PolarsError::ColumnNotFound(..) => {
-                // Return BadRequest if specified column doesn't exist
-                return Ok(plain_response(StatusCode::BadRequest, "Column not found"));
-            }
-            _ => {
-                // Return InternalServerError for other column-related errors
+        Err(PolarsError::ColumnNotFound(..)) => {
+            return Ok(plain_response(StatusCode::BadRequest, "Column not found"));
+        }
+        Err(e) => {
+            return Ok(plain_response(
+                StatusCode::InternalServerError,
+                format!("Column error {:?}", e.to_string()).as_str(),
+            ));
+        }
+    };
+
+    let filename = format!("{}_compliance", key);
+    Ok(dataframe_response(dataframe, &output, &filename))
+}
+
+// Defines a structure to parse query parameters from a request.
+#[derive(Deserialize, Debug)]
+struct AggregateQuery {
+    every: Option<String>, // Optional bucket length, e.g. 1h, 8h or 1d. Defaults to 1h
+    offset: Option<String>, // Optional shift of the buckets from local midnight, e.g. 6h
+    columns: Option<String>, // Optional comma-separated string of columns to aggregate
+    agg: Option<String>,   // Optional comma-separated aggregations, e.g. mean,min,max
+    filter: Option<String>, // Optional row filter, e.g. wall_min<0.2 and info5=="Anna"
+}
+
+/// Aggregates measured columns of a key over time buckets of `local_time`, e.g. hourly means
+/// for trend charts. Buckets follow the configured time zone, so daily buckets start at local
+/// midnight.
+async fn measurement_aggregate(req: Request<AppState>) -> tide::Result {
+    let query: AggregateQuery = req.query()?;
+    let output = match requested_output(&req) {
+        Ok(output) => output,
+        Err((code, msg)) => return Ok(plain_response(code, &msg)),
+    };
+    let (key, mut lazyframe) = match requested_measurements(&req) {
+        Ok(measurements) => measurements,
+        Err((code, msg)) => return Ok(plain_response(code, &msg)),
+    };
+
+    let aggregations = match &query.agg {
+        Some(agg) => match agg
+            .split(',')
+            .map(str::parse::<Aggregation>)
+            .collect::<Result<Vec<Aggregation>, _>>()
+        {
+            Ok(aggregations) => aggregations,
+            Err(e) => return Ok(plain_response(StatusCode::BadRequest, &e.to_string())),
+        },
+        None => DEFAULT_AGGREGATIONS.to_vec(),
+    };
+
+    let columns: Vec<&str> = match &query.columns {
+        Some(columns) => columns.split(',').collect(),
+        None => match lazyframe.collect_schema() {
+            Ok(schema) => DEFAULT_COLUMNS
+                .into_iter()
+                .filter(|column| schema.contains(column))
+                .collect(),
+            Err(e) => {
+                log::error!("Error reading the columns of {}: {:#}", key, e);
                 return Ok(plain_response(
                     StatusCode::InternalServerError,
-                    format!("Column errror {:?}", e.to_string()).as_str(),
+                    "Error reading columns",
                 ));
             }
         },
     };
 
-    // Convert the final dataframe to JSON and use it as the response
-    Ok(dataframe_to_json_response(&mut dataframe))
+    // Keep the rows matching the optional filter
+    let lazyframe = match filter_rows(lazyframe, query.filter.as_deref()) {
+        Ok(lazyframe) => lazyframe,
+        Err((code, msg)) => return Ok(plain_response(code, &msg)),
+    };
+
+    let result = match aggregate(
+        lazyframe,
+        query.every.as_deref().unwrap_or("1h"),
+        query.offset.as_deref().unwrap_or("0h"),
+        &columns,
+        &aggregations,
+    ) {
+        Ok(result) => result,
+        Err(e) if e.is_request_error() => {
+            return Ok(plain_response(StatusCode::BadRequest, &format!("{:#}", e)));
+        }
+        Err(e) => {
+            log::error!("Error aggregating measurements of {}: {:#}", key, e);
+            return Ok(plain_response(
+                StatusCode::InternalServerError,
+                "Error aggregating measurements",
+            ));
+        }
+    };
+
+    let filename = format!("{}_aggregate", key);
+    Ok(dataframe_response(result, &output, &filename))
+}
+
+// Defines a structure to parse query parameters from a request.
+#[derive(Deserialize, Debug)]
+struct StatsQuery {
+    columns: Option<String>, // Optional comma-separated string of columns to evaluate
+    percentiles: Option<String>, // Optional comma-separated string of percentiles
+}
+
+/// Provides capability statistics (mean, sigma, Cp, Cpk and percentiles) of measured columns,
+/// using the minimum and maximum limits in the article parameters of the same key. Without a
+/// maximum, Cp is null and Cpk is the one-sided index of the minimum, unless `symmetric=true`
+/// mirrors the minimum around the nominal value.
+/// Without a `columns` parameter the default columns present in the data are evaluated.
+/// The time range is given by `start` and `end` times, e.g. `2023-11-15T06:00:00Z`, or by the
+/// whole days of `start_date` and `end_date`.
+async fn stats(req: Request<AppState>) -> tide::Result {
+    let query: StatsQuery = req.query()?;
+    let (key, lazyframe) = match requested_measurements(&req) {
+        Ok(measurements) => measurements,
+        Err((code, msg)) => return Ok(plain_response(code, &msg)),
+    };
+
+    let percentiles = match &query.percentiles {
+        Some(percentiles) => match percentiles
+            .split(',')
+            .map(|p| p.trim().parse::<f64>())
+            .collect::<Result<Vec<f64>, _>>()
+        {
+            Ok(percentiles) => percentiles,
+            Err(_) => {
+                return Ok(plain_response(
+                    StatusCode::BadRequest,
+                    "Invalid percentiles",
+                ))
+            }
+        },
+        None => DEFAULT_PERCENTILES.to_vec(),
+    };
+
+    // Statistics without limits are still useful, so a missing article is not an error
+    let article = match req.state().parameter_data.data.get(&key) {
+        Some(ksmfile) => match Article::from_dataframe(&ksmfile.dataframe) {
+            Ok(article) => article,
+            Err(e) => {
+                log::warn!("Ignoring invalid parameters for {}: {:#}", key, e);
+                Article::default()
+            }
+        },
+        None => Article::default(),
+    };
+    let article = requested_limits(&req, article)?;
+
+    let dataframe = match lazyframe.collect() {
+        Ok(df) => df,
+        Err(e) => {
+            log::error!("Error filtering measurements of {}: {:#}", key, e);
+            return Ok(plain_response(
+                StatusCode::InternalServerError,
+                "Error filtering measurements",
+            ));
+        }
+    };
+
+    let columns: Vec<&str> = match &query.columns {
+        Some(columns) => columns.split(',').collect(),
+        None => DEFAULT_COLUMNS
+            .into_iter()
+            .filter(|column| dataframe.get_column_index(column).is_some())
+            .collect(),
+    };
+
+    let statistics = match article_capability(&dataframe, &article, &columns, &percentiles) {
+        Ok(statistics) => statistics,
+        Err(e) if e.is_request_error() => {
+            return Ok(plain_response(StatusCode::BadRequest, &format!("{:#}", e)));
+        }
+        Err(e) => {
+            log::error!("Error computing statistics of {}: {:#}", key, e);
+            return Ok(plain_response(
+                StatusCode::InternalServerError,
+                "Error computing statistics",
+            ));
+        }
+    };
+
+    match serde_json::to_string(&statistics) {
+        Ok(json) => Ok(Response::builder(StatusCode::Ok)
+            .body(json)
+            .content_type(tide::http::mime::JSON)
+            .build()),
+        Err(_) => Ok(plain_response(
+            StatusCode::InternalServerError,
+            "Error converting to json",
+        )),
+    }
 }
 
 #[derive(Deserialize, Debug)]
 struct ParameterQuery {
     columns: Option<String>,
 }
-async fn parameters(req: Request<AppState<'_>>) -> tide::Result {
+async fn parameters(req: Request<AppState>) -> tide::Result {
     let query: ParameterQuery = req.query()?;
+    let output = match requested_output(&req) {
+        Ok(output) => output,
+        Err((code, msg)) => return Ok(plain_response(code, &msg)),
+    };
     let data = &req.state().parameter_data;
 
-    let key = match req.param("name") {
-        Ok(file) => file, //format!("{}.art", file),
-        Err(_) => {
+    let key = match file_key(&req) {
+        Some(key) => key,
+        None => {
             log::error!("Invalid key for parameters request");
             // Return a BadRequest response if filename parameter is missing or incorrect
             return Ok(plain_response(StatusCode::BadRequest, "Invalid parameter"));
         }
     };
 
-    let lazyframe = match data.data.get(key) {
+    let lazyframe = match data.data.get(&key) {
         Some(ksmfile) => ksmfile.dataframe.clone().lazy(),
         None => {
             log::error!("Invalid parameter entry requested: {}", key);
@@ -292,7 +914,7 @@ async fn parameters(req: Request<AppState<'_>>) -> tide::Result {
     };
 
     let column_string = query.columns.unwrap_or_default();
-    let mut dataframe = match select_dataframe_columns(lazyframe, &column_string) {
+    let dataframe = match select_dataframe_columns(lazyframe, &column_string) {
         Ok(df) => df,
         Err(e) => match e {
             PolarsError::ColumnNotFound(..) => {
@@ -318,27 +940,26 @@ async fn parameters(req: Request<AppState<'_>>) -> tide::Result {
         },
     };
 
-    Ok(dataframe_to_json_response(&mut dataframe))
+    let filename = format!("{}_parameters", key);
+    Ok(dataframe_response(dataframe, &output, &filename))
 }
 
 /// Provides a list of the resistance parameter for all .art files.
-/// Formatted as a list of article number / resistance pairs
-async fn view_parameter_resistance(req: Request<AppState<'_>>) -> tide::Result {
-    let mut resistances: Vec<(String, String)> = Vec::new();
+/// Formatted as a list of article number / resistance pairs, with the resistance as a number
+async fn view_parameter_resistance(req: Request<AppState>) -> tide::Result {
+    let mut resistances: Vec<(String, f64)> = Vec::new();
     let parameter_data = &req.state().parameter_data;
     //Iterate over all .art files
     for entry in parameter_data.data.iter() {
-        let lazyframe = entry.value().dataframe.clone().lazy();
-        let collected = match lazyframe
-            .select([col("info6"), col("check_user2_maxlimit")])
-            .collect()
-        {
-            Ok(df) => df,
-            Err(_) => continue,
+        //Read conductor resistance and article number, ignoring any other parameter
+        let dataframe = &entry.value().dataframe;
+        let (Some(info6), Some(resistance)) = (
+            first_value_as_text(dataframe, "info6"),
+            first_value_as_float(dataframe, "check_user2_maxlimit"),
+        ) else {
+            continue;
         };
-        //Read conductor resistance and article number columns
-        let art_no = format!("{:0>5}", first_value_or_empty_string(&collected, "info6"));
-        let resistance = first_value_or_empty_string(&collected, "check_user2_maxlimit");
+        let art_no = format!("{:0>5}", info6);
         // Push values to resulting Vec
         resistances.push((art_no, resistance));
     }
@@ -353,23 +974,30 @@ async fn view_parameter_resistance(req: Request<AppState<'_>>) -> tide::Result {
         }
     };
 
-    return Ok(plain_response(StatusCode::Ok, &json_string));
+    Ok(plain_response(StatusCode::Ok, &json_string))
 }
 
-/// Returns the first value from a specified column in a data frame, or an empty string if not available.
-fn first_value_or_empty_string(df: &DataFrame, column_name: &str) -> String {
-    // Attempt to access the specified column in the data frame.
-    match df.column(column_name) {
-        Ok(column) => {
-            // Attempt to retrieve string values from the column.
-            match column.str() {
-                Ok(strval) => strval.get(0).unwrap_or_default().to_string(),
-                Err(_) => String::new(), // Return an empty string on error accessing strings.
-            }
-        }
-        Err(_) => String::new(), // Return an empty string if the column is not found.
+/// Returns the first value of a column as text, or `None` if the column is missing or the value
+/// is null or empty.
+fn first_value_as_text(dataframe: &DataFrame, column_name: &str) -> Option<String> {
+    let column = dataframe.column(column_name).ok()?;
+    let text = match column.dtype() {
+        DataType::String => column.str().ok()?.get(0)?.to_string(),
+        _ => column.get(0).ok()?.to_string(),
+    };
+    (!text.is_empty()).then_some(text)
+}
+
+/// Returns the first value of a numeric column, or `None` if the column is missing, not numeric
+/// or the value is null.
+fn first_value_as_float(dataframe: &DataFrame, column_name: &str) -> Option<f64> {
+    let column = dataframe.column(column_name).ok()?;
+    if !column.dtype().is_primitive_numeric() {
+        return None;
     }
+    column.cast(&DataType::Float64).ok()?.f64().ok()?.get(0)
 }
+
 //
 // Defines a structure to parse query parameters from a request.
 #[derive(Deserialize, Debug)]
@@ -378,15 +1006,40 @@ struct ViewOperatorMeasurementQuery {
     end_date: Option<NaiveDate>,   // Optional end date for filtering dataframe
 }
 
-async fn view_operator_measurement(req: Request<AppState<'_>>) -> tide::Result {
+async fn view_operator_measurement(req: Request<AppState>) -> tide::Result {
     let query: ViewOperatorMeasurementQuery = req.query()?;
+    let output = match requested_output(&req) {
+        Ok(output) => output,
+        Err((code, msg)) => return Ok(plain_response(code, &msg)),
+    };
     let data = &req.state().measurement_data;
     let mut result_df = DataFrame::default();
-    let column_names = "info6,info4,info5,measure_time1970,checkresult".to_string();
+    let column_names = "info6,info4,info5,measure_time1970";
+    // The gauge's own result and the check outcomes decoded from it only exist in files with a
+    // checkresult column, and are null for the rows of other files
+    let with_checks = std::iter::once(CHECKRESULT_COLUMN.to_string())
+        .chain(
+            data.options()
+                .schema
+                .checks()
+                .checks()
+                .iter()
+                .map(|check| CheckResultMapping::column_name(&check.name)),
+        )
+        .fold(column_names.to_string(), |names, name| names + "," + &name);
 
-    for art_entry in data.data.iter() {
+    // Includes the history of every key, like the other measurement endpoints
+    for key in data.keys() {
+        let Some(art_dataframe) = data.dataframe(&key) else {
+            continue;
+        };
+        let column_names = if art_dataframe.get_column_index(CHECKRESULT_COLUMN).is_some() {
+            with_checks.as_str()
+        } else {
+            column_names
+        };
         //Read article dataframe as lazyframe
-        let lazy = art_entry.dataframe.clone().lazy();
+        let lazy = art_dataframe.lazy();
         //Adjust to GMT+1
         let lazy = lazy.with_column(col("measure_time1970") + lit(3600));
 
@@ -406,7 +1059,7 @@ async fn view_operator_measurement(req: Request<AppState<'_>>) -> tide::Result {
             }
         };
         // Process the column filtering
-        let dataframe = match select_dataframe_columns(lazy, column_names.as_str()) {
+        let dataframe = match select_dataframe_columns(lazy, column_names) {
             Ok(df) => df,
             Err(e) => match e {
                 PolarsError::ColumnNotFound(..) => {
@@ -423,7 +1076,8 @@ async fn view_operator_measurement(req: Request<AppState<'_>>) -> tide::Result {
             },
         };
 
-        result_df = match result_df.vstack(&dataframe) {
+        // Aligns columns with differing data types across articles
+        result_df = match append_entries(&result_df, dataframe) {
             Ok(df) => df,
             Err(_) => {
                 return Ok(plain_response(
@@ -433,19 +1087,78 @@ async fn view_operator_measurement(req: Request<AppState<'_>>) -> tide::Result {
             }
         };
     }
-    if let Err(e) = result_df.set_column_names([
-        PlSmallStr::from_str("artno"),
-        PlSmallStr::from_str("machine"),
-        PlSmallStr::from_str("operator"),
-        PlSmallStr::from_str("time"),
-        PlSmallStr::from_str("result"),
-    ]) {
-        let msg = format!("Error when setting column names {}", e);
-        return Ok(plain_response(
-            StatusCode::InternalServerError,
-            msg.as_str(),
-        ));
+    // The check columns keep their names and follow the renamed columns
+    let new_column_names = ["artno", "machine", "operator", "time", "result"];
+    for (old, new) in with_checks.split(',').zip(new_column_names) {
+        if old == CHECKRESULT_COLUMN && result_df.get_column_index(old).is_none() {
+            continue;
+        }
+        if let Err(e) = result_df.rename(old, PlSmallStr::from_str(new)) {
+            let msg = format!("Error when setting column names {}", e);
+            return Ok(plain_response(
+                StatusCode::InternalServerError,
+                msg.as_str(),
+            ));
+        }
     }
 
-    Ok(dataframe_to_json_response(&mut result_df))
+    Ok(dataframe_response(
+        result_df,
+        &output,
+        "operator_measurement",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_sort_orders() {
+        let (columns, descending) = parse_sort("measure_time1970:desc,wall_min").unwrap();
+        assert_eq!(columns, ["measure_time1970", "wall_min"]);
+        assert_eq!(descending, [true, false]);
+
+        let (columns, descending) = parse_sort("info5:asc").unwrap();
+        assert_eq!(columns, ["info5"]);
+        assert_eq!(descending, [false]);
+
+        assert!(parse_sort("wall_min:up").is_err());
+        assert!(parse_sort(":desc").is_err());
+        assert!(parse_sort("wall_min,").is_err());
+    }
+
+    #[test]
+    fn slice_pages() {
+        let dataframe = df!("wall_min" => [1.0, 2.0, 3.0, 4.0, 5.0]).unwrap();
+        let page = |offset, limit, tail| {
+            let page = PageQuery {
+                sort: None,
+                offset,
+                limit,
+                tail,
+            };
+            let result = page.slice(dataframe.clone().lazy(), 5).collect().unwrap();
+            let values: Vec<Option<f64>> = result
+                .column("wall_min")
+                .unwrap()
+                .f64()
+                .unwrap()
+                .into_iter()
+                .collect();
+            values.into_iter().flatten().collect::<Vec<f64>>()
+        };
+
+        assert_eq!(page(None, None, None), [1.0, 2.0, 3.0, 4.0, 5.0]);
+        assert_eq!(page(Some(1), Some(2), None), [2.0, 3.0]);
+        assert_eq!(page(Some(3), Some(10), None), [4.0, 5.0]);
+        // Pages past the end and empty pages have no rows
+        assert!(page(Some(5), None, None).is_empty());
+        assert!(page(Some(u64::MAX), Some(2), None).is_empty());
+        assert!(page(None, Some(0), None).is_empty());
+        // The tail is limited to the rows there are
+        assert_eq!(page(None, None, Some(2)), [4.0, 5.0]);
+        assert_eq!(page(None, None, Some(10)), [1.0, 2.0, 3.0, 4.0, 5.0]);
+        assert!(page(None, None, Some(0)).is_empty());
+    }
 }
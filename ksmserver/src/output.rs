@@ -0,0 +1,409 @@
+/// Module for writing DataFrames in the output formats supported by the endpoints.
+///
+/// The format of a response is chosen with the `format` query parameter, or otherwise with the
+/// `Accept` header of the request. JSON is returned when neither asks for a supported format.
+///
+/// Responses are streamed: the DataFrame is written in chunks on a blocking thread while the
+/// chunks already written are sent, so the body is never held in memory as a whole.
+use async_std::channel::{self, Receiver, Sender};
+use async_std::io::{BufRead as AsyncBufRead, Read as AsyncRead};
+use async_std::stream::Stream;
+use async_std::task;
+use polars::prelude::*;
+use polars_io::ipc::IpcWriter;
+use polars_io::json::{JsonFormat, JsonWriter};
+use polars_io::parquet::write::ParquetWriter;
+use std::borrow::Cow;
+use std::fmt;
+use std::io::{self, Write};
+use std::pin::Pin;
+use std::str::FromStr;
+use std::task::{Context, Poll};
+use tide::log;
+
+/// Size of the chunks a streamed DataFrame is sent in
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Number of written chunks waiting to be sent before the writer blocks
+const CHUNKS_IN_FLIGHT: usize = 4;
+
+/// Output formats of the endpoints returning DataFrames
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    /// The original response format: one JSON object per row, separated by newlines
+    #[default]
+    Json,
+    /// Newline delimited JSON. The body equals `Json`, but is labeled as NDJSON.
+    NdJson,
+    Csv,
+    Parquet,
+    /// Arrow IPC file format
+    Ipc,
+}
+
+impl OutputFormat {
+    /// Returns the media type sent in the `Content-Type` header.
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            OutputFormat::Json => "application/json",
+            OutputFormat::NdJson => "application/x-ndjson",
+            OutputFormat::Csv => "text/csv; charset=utf-8",
+            OutputFormat::Parquet => "application/vnd.apache.parquet",
+            OutputFormat::Ipc => "application/vnd.apache.arrow.file",
+        }
+    }
+
+    /// Returns the file extension of download filenames.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Json => "json",
+            OutputFormat::NdJson => "ndjson",
+            OutputFormat::Csv => "csv",
+            OutputFormat::Parquet => "parquet",
+            OutputFormat::Ipc => "arrow",
+        }
+    }
+
+    /// Returns the format of a media type, e.g. `text/csv`. Wildcards select JSON, except for
+    /// `text/*` which selects CSV.
+    pub fn from_media_type(media_type: &str) -> Option<OutputFormat> {
+        match media_type.trim().to_ascii_lowercase().as_str() {
+            "application/json" | "*/*" | "application/*" => Some(OutputFormat::Json),
+            "application/x-ndjson" | "application/ndjson" | "application/jsonl" => {
+                Some(OutputFormat::NdJson)
+            }
+            "text/csv" | "text/*" => Some(OutputFormat::Csv),
+            "application/vnd.apache.parquet" | "application/x-parquet" => {
+                Some(OutputFormat::Parquet)
+            }
+            "application/vnd.apache.arrow.file" | "application/x-arrow" => Some(OutputFormat::Ipc),
+            _ => None,
+        }
+    }
+
+    /// Returns the preferred supported format of an `Accept` header, taking quality values
+    /// into account. Media types with equal quality are preferred in the order listed.
+    ///
+    /// Returns `None` if the header lists no supported media type, in which case the endpoints
+    /// fall back to JSON.
+    pub fn from_accept(accept: &str) -> Option<OutputFormat> {
+        let mut accepted: Vec<(f32, OutputFormat)> = accept
+            .split(',')
+            .filter_map(|entry| {
+                let mut parts = entry.split(';');
+                let format = OutputFormat::from_media_type(parts.next()?)?;
+                let quality = parts
+                    .filter_map(|param| param.trim().strip_prefix("q="))
+                    .find_map(|q| q.trim().parse::<f32>().ok())
+                    .unwrap_or(1.0);
+                (quality > 0.0).then_some((quality, format))
+            })
+            .collect();
+        // Stable sort keeps the listed order for equal qualities
+        accepted.sort_by(|a, b| b.0.total_cmp(&a.0));
+        accepted.first().map(|(_, format)| *format)
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    /// Parses the name of a format as used in the `format` query parameter.
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "json" => Ok(OutputFormat::Json),
+            "ndjson" | "jsonl" => Ok(OutputFormat::NdJson),
+            "csv" => Ok(OutputFormat::Csv),
+            "parquet" => Ok(OutputFormat::Parquet),
+            "arrow" | "ipc" => Ok(OutputFormat::Ipc),
+            _ => Err(format!(
+                "Unknown format '{}', expected json, ndjson, csv, parquet or arrow",
+                name
+            )),
+        }
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.extension())
+    }
+}
+
+/// Settings of CSV output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CsvOptions {
+    /// Field delimiter, e.g. `;` for spreadsheets using a decimal comma
+    pub delimiter: u8,
+    /// Writes floats with a decimal comma instead of a decimal point
+    pub decimal_comma: bool,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        CsvOptions {
+            delimiter: b',',
+            decimal_comma: false,
+        }
+    }
+}
+
+/// Parses a CSV delimiter given as a single ASCII character, or `tab`.
+pub fn parse_delimiter(value: &str) -> Result<u8, String> {
+    match value {
+        "tab" | "\\t" | "\t" => Ok(b'\t'),
+        _ if value.len() == 1 && value.is_ascii() && value != "\"" && value != "\n" => {
+            Ok(value.as_bytes()[0])
+        }
+        _ => Err(format!(
+            "Invalid delimiter '{}', expected a single character or tab",
+            value
+        )),
+    }
+}
+
+/// Writes a DataFrame in the given format.
+///
+/// # Errors
+/// Returns the `PolarsError` of the writer if the DataFrame cannot be written.
+pub fn write_dataframe<W: Write>(
+    dataframe: &mut DataFrame,
+    format: OutputFormat,
+    csv: &CsvOptions,
+    writer: W,
+) -> PolarsResult<()> {
+    match format {
+        // Existing clients read the JSON lines written by default
+        OutputFormat::Json | OutputFormat::NdJson => JsonWriter::new(writer)
+            .with_json_format(JsonFormat::JsonLines)
+            .finish(dataframe),
+        OutputFormat::Csv if csv.decimal_comma => {
+            let mut dataframe = with_decimal_comma(dataframe)?;
+            CsvWriter::new(writer)
+                .with_separator(csv.delimiter)
+                .finish(&mut dataframe)
+        }
+        OutputFormat::Csv => CsvWriter::new(writer)
+            .with_separator(csv.delimiter)
+            .finish(dataframe),
+        OutputFormat::Parquet => ParquetWriter::new(writer).finish(dataframe).map(|_| ()),
+        OutputFormat::Ipc => IpcWriter::new(writer).finish(dataframe),
+    }
+}
+
+/// Writes a DataFrame in the given format on a blocking thread and returns a reader of the
+/// written bytes, for use as a streamed response body.
+///
+/// The writer waits while the reader falls behind, and stops when the reader is dropped. If the
+/// DataFrame cannot be written, the error is logged and the reader fails, so that the response
+/// is aborted instead of ending with a truncated body.
+pub fn stream_dataframe(
+    mut dataframe: DataFrame,
+    format: OutputFormat,
+    csv: CsvOptions,
+) -> ChunkReader {
+    let (sender, receiver) = channel::bounded(CHUNKS_IN_FLIGHT);
+    task::spawn_blocking(move || {
+        let mut writer = ChunkWriter {
+            sender: sender.clone(),
+            buffer: Vec::with_capacity(CHUNK_SIZE),
+        };
+        let result = write_dataframe(&mut dataframe, format, &csv, &mut writer)
+            .and_then(|_| writer.flush().map_err(PolarsError::from));
+        if let Err(e) = result {
+            log::error!("Failed to write {} response: {}", format, e);
+            let _ = sender.send_blocking(Err(io::Error::other(e.to_string())));
+        }
+    });
+    ChunkReader {
+        receiver,
+        chunk: Vec::new(),
+        position: 0,
+    }
+}
+
+/// Sends the bytes written to it in chunks of `CHUNK_SIZE` to a `ChunkReader`.
+struct ChunkWriter {
+    sender: Sender<io::Result<Vec<u8>>>,
+    buffer: Vec<u8>,
+}
+
+impl Write for ChunkWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        if self.buffer.len() >= CHUNK_SIZE {
+            self.flush()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let chunk = std::mem::replace(&mut self.buffer, Vec::with_capacity(CHUNK_SIZE));
+        self.sender
+            .send_blocking(Ok(chunk))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "Response was dropped"))
+    }
+}
+
+/// Reads the chunks of a DataFrame written by `stream_dataframe`.
+pub struct ChunkReader {
+    receiver: Receiver<io::Result<Vec<u8>>>,
+    chunk: Vec<u8>,
+    position: usize,
+}
+
+impl AsyncBufRead for ChunkReader {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        let this = self.get_mut();
+        while this.position == this.chunk.len() {
+            match Pin::new(&mut this.receiver).poll_next(cx) {
+                Poll::Ready(Some(Ok(chunk))) => {
+                    this.chunk = chunk;
+                    this.position = 0;
+                }
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Err(e)),
+                // The writer is done once every sender is dropped
+                Poll::Ready(None) => return Poll::Ready(Ok(&[])),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        Poll::Ready(Ok(&this.chunk[this.position..]))
+    }
+
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        let this = self.get_mut();
+        this.position = (this.position + amt).min(this.chunk.len());
+    }
+}
+
+impl AsyncRead for ChunkReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let available = match self.as_mut().poll_fill_buf(cx) {
+            Poll::Ready(Ok(available)) => available,
+            Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+            Poll::Pending => return Poll::Pending,
+        };
+        let length = available.len().min(buf.len());
+        buf[..length].copy_from_slice(&available[..length]);
+        self.consume(length);
+        Poll::Ready(Ok(length))
+    }
+}
+
+/// Returns a copy of the DataFrame with its float columns converted to strings using a decimal
+/// comma.
+fn with_decimal_comma(dataframe: &DataFrame) -> PolarsResult<DataFrame> {
+    let mut result = dataframe.clone();
+    for column in dataframe.get_columns() {
+        if !column.dtype().is_float() {
+            continue;
+        }
+        let strings = column.cast(&DataType::String)?;
+        let strings = strings
+            .str()?
+            .apply_values(|value| Cow::Owned(value.replace('.', ",")));
+        result.with_column(strings.into_column())?;
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_std::io::ReadExt;
+
+    #[test]
+    fn select_format_from_accept() {
+        assert_eq!(
+            OutputFormat::from_accept("text/csv"),
+            Some(OutputFormat::Csv)
+        );
+        // The highest quality wins, regardless of the order
+        assert_eq!(
+            OutputFormat::from_accept("application/json;q=0.5, application/vnd.apache.parquet"),
+            Some(OutputFormat::Parquet)
+        );
+        assert_eq!(
+            OutputFormat::from_accept("text/csv; q=0.2, application/x-ndjson; q=0.8"),
+            Some(OutputFormat::NdJson)
+        );
+        // Equal qualities keep the listed order
+        assert_eq!(
+            OutputFormat::from_accept("application/x-arrow, text/csv"),
+            Some(OutputFormat::Ipc)
+        );
+        // A quality of 0 excludes the media type
+        assert_eq!(
+            OutputFormat::from_accept("text/csv;q=0, */*;q=0.1"),
+            Some(OutputFormat::Json)
+        );
+        // Unsupported media types are ignored, leaving the endpoints to fall back to JSON
+        assert_eq!(
+            OutputFormat::from_accept("text/html, application/xml;q=0.9"),
+            None
+        );
+        assert_eq!(OutputFormat::from_accept("text/csv;q=0"), None);
+        assert_eq!(OutputFormat::from_accept(""), None);
+    }
+
+    fn csv_output(csv: CsvOptions) -> String {
+        let mut dataframe = df!(
+            "info5" => ["Anna", "Bo"],
+            "wall_min" => [0.25, 1.5],
+            "cable_parts" => [1i64, 2],
+        )
+        .unwrap();
+        let mut written = Vec::new();
+        write_dataframe(&mut dataframe, OutputFormat::Csv, &csv, &mut written).unwrap();
+
+        // The streamed body equals the written one
+        let mut streamed = String::new();
+        task::block_on(
+            stream_dataframe(dataframe, OutputFormat::Csv, csv).read_to_string(&mut streamed),
+        )
+        .unwrap();
+        assert_eq!(streamed.as_bytes(), written);
+        streamed
+    }
+
+    #[test]
+    fn write_csv_options() {
+        assert_eq!(
+            csv_output(CsvOptions::default()),
+            "info5,wall_min,cable_parts\nAnna,0.25,1\nBo,1.5,2\n"
+        );
+        let csv = CsvOptions {
+            delimiter: parse_delimiter("tab").unwrap(),
+            decimal_comma: false,
+        };
+        assert_eq!(
+            csv_output(csv),
+            "info5\twall_min\tcable_parts\nAnna\t0.25\t1\nBo\t1.5\t2\n"
+        );
+        // Only floats are written with a decimal comma
+        let csv = CsvOptions {
+            delimiter: b';',
+            decimal_comma: true,
+        };
+        assert_eq!(
+            csv_output(csv),
+            "info5;wall_min;cable_parts\nAnna;0,25;1\nBo;1,5;2\n"
+        );
+    }
+
+    #[test]
+    fn parse_delimiters() {
+        assert_eq!(parse_delimiter(";"), Ok(b';'));
+        assert_eq!(parse_delimiter("tab"), Ok(b'\t'));
+        assert!(parse_delimiter("\"").is_err());
+        assert!(parse_delimiter(";;").is_err());
+        assert!(parse_delimiter("").is_err());
+    }
+}